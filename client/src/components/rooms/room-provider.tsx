@@ -1,8 +1,8 @@
-import { Problem, TestCase } from "@/types/problem";
+import { Problem } from "@/types/problem";
 import { PublicUser } from "@/types/user";
 import { BACKEND_URL } from "@/utils/consts";
 import { Enum } from "@/utils/enum";
-import { Text } from "@chakra-ui/react";
+import { Text, useToast } from "@chakra-ui/react";
 import { useQuery } from "@tanstack/react-query";
 import { AxiosError } from "axios";
 import { useRouter } from "next/router";
@@ -58,6 +58,7 @@ type ServerSentCommand = Enum<{
   SetRoomConfig: RoomConfig;
   SetProblems: Problem[] | null;
   SetTestResponse: TestResponse;
+  Error: string;
 }>;
 
 type ClientSentCommand = Enum<{
@@ -82,17 +83,38 @@ type TestStatus = Enum<{
   Response: TestResponse;
 }>;
 
+export type Verdict = Enum<{
+  Accepted: null;
+  WrongAnswer: {
+    output: string;
+  };
+  RuntimeError: {
+    traceback: string;
+  };
+  TimeLimitExceeded: null;
+  MemoryLimitExceeded: null;
+  NotRun: null;
+}>;
+
+export type TestCaseResult = {
+  input: string;
+  expected: string;
+  verdict: Verdict;
+  runtime: number;
+  hidden: boolean;
+};
+
 type TestResponse = Enum<{
   Error: {
     message: string;
   };
   Ran: {
-    failedTests: (TestCase & { expected: string })[];
-    okayTests: TestCase[];
+    results: TestCaseResult[];
   };
   AllTestsPassed: {
     runtime: number;
   };
+  Cancelled: null;
 }>;
 
 export const useRoomData = create<{
@@ -208,6 +230,7 @@ export const RoomProvider: React.FC<
   const setRoomConfig = useRoomData((s) => s.setRoomConfig);
   const setProblems = useRoomData((s) => s.setProblems);
   const setTestStatus = useRoomData((s) => s.setTestStatus);
+  const toast = useToast();
 
   useEffect(() => {
     if (
@@ -245,6 +268,8 @@ export const RoomProvider: React.FC<
           t: "Response",
           c: data.c,
         });
+      } else if (data.t === "Error") {
+        toast({ title: data.c, status: "error" });
       }
     };
 
@@ -288,6 +313,7 @@ export const RoomProvider: React.FC<
     setRoomConfig,
     setProblems,
     setTestStatus,
+    toast,
   ]);
 
   if (canConnectQuery.status === "loading") return <Text>Loading</Text>;
@@ -10,11 +10,60 @@ import {
 } from "@chakra-ui/react";
 import { FiCheck, FiX } from "react-icons/fi";
 import ReactConfetti from "react-confetti";
-import { useRoomData } from "./room-provider";
+import { TestCaseResult, useRoomData } from "./room-provider";
 import { useState } from "react";
 
+const FailedTest: React.FC<{ result: TestCaseResult }> = ({ result }) => {
+  const { verdict } = result;
+
+  return (
+    <Box
+      border="solid 2px"
+      borderColor="red.500"
+      w="full"
+      p="2"
+      borderRadius="md"
+    >
+      {result.hidden ? (
+        <Code bgColor="gray.800">Hidden test case</Code>
+      ) : (
+        <>
+          <Code bgColor="gray.800">Input: {result.input}</Code> <br />
+          <Code bgColor="gray.800">Expected: {result.expected}</Code>
+        </>
+      )}
+      <br />
+      {verdict.t === "WrongAnswer" && (
+        <Code bgColor="gray.800">Got: {verdict.c.output}</Code>
+      )}
+      {verdict.t === "RuntimeError" && (
+        <Code bgColor="gray.800" whiteSpace="pre-wrap" color="red.400">
+          {verdict.c.traceback}
+        </Code>
+      )}
+      {verdict.t === "TimeLimitExceeded" && (
+        <Code bgColor="gray.800">Time limit exceeded</Code>
+      )}
+      {verdict.t === "MemoryLimitExceeded" && (
+        <Code bgColor="gray.800">Memory limit exceeded</Code>
+      )}
+      {verdict.t === "NotRun" && <Code bgColor="gray.800">Not run</Code>}
+    </Box>
+  );
+};
+
 export const TestResults: React.FC = () => {
   const testStatus = useRoomData((s) => s.testStatus);
+  const results =
+    testStatus.t === "Response" && testStatus.c.t === "Ran"
+      ? testStatus.c.c.results
+      : [];
+  const failedTests = results.filter(
+    (result) => result.verdict.t !== "Accepted"
+  );
+  const okayTests = results.filter(
+    (result) => result.verdict.t === "Accepted"
+  );
 
   const confetti =
     testStatus.t === "Response" && testStatus.c.t === "AllTestsPassed";
@@ -37,32 +86,20 @@ export const TestResults: React.FC = () => {
       )}
       {testStatus.t === "Response" && testStatus.c.t === "Ran" && (
         <>
-          {testStatus.c.c.failedTests.length > 0 && (
+          {failedTests.length > 0 && (
             <Heading fontSize="1.4rem" mb="3">
               Failed
             </Heading>
           )}
           <VStack alignItems="start">
-            {testStatus.c.c.failedTests.map((testCase, i) => (
-              <Box
-                key={i}
-                border="solid 2px"
-                borderColor="red.500"
-                w="full"
-                p="2"
-                borderRadius="md"
-              >
-                <Code bgColor="gray.800">Input: {testCase.input}</Code> <br />
-                <Code bgColor="gray.800">Expected: {testCase.expected}</Code>
-                <br />
-                <Code bgColor="gray.800">Got: {testCase.output}</Code>
-              </Box>
+            {failedTests.map((result, i) => (
+              <FailedTest key={i} result={result} />
             ))}
           </VStack>
 
-          {testStatus.c.c.okayTests.length > 0 && (
+          {okayTests.length > 0 && (
             <>
-              {testStatus.c.c.failedTests.length > 0 && (
+              {failedTests.length > 0 && (
                 <hr style={{ margin: "1rem 0" }} />
               )}
               <Heading fontSize="1.4rem" mb="3">
@@ -71,7 +108,7 @@ export const TestResults: React.FC = () => {
             </>
           )}
 
-          {testStatus.c.c.okayTests.map((testCase, i) => (
+          {okayTests.map((result, i) => (
             <HStack
               key={i}
               border="solid 2px"
@@ -83,7 +120,9 @@ export const TestResults: React.FC = () => {
             >
               <FiCheck />
               <Code bgColor="gray.800">
-                {testCase.input} =&gt; {testCase.output}
+                {result.hidden
+                  ? "Hidden test case"
+                  : `${result.input} => ${result.expected}`}
               </Code>
             </HStack>
           ))}
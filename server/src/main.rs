@@ -18,8 +18,12 @@ pub use utils::{errors, mongo};
 use axum::{Extension, Router, Server};
 
 use crate::{
+    models::problem::ProblemRepo,
     mongo::Db,
-    routers::{auth::auth_routes, problems::problem_routes, rooms::room_routes},
+    routers::{
+        auth::auth_routes, matches::match_routes, problems::problem_routes, ratings::rating_routes,
+        rooms::room_routes, submissions::submission_routes,
+    },
 };
 
 #[derive(Default)]
@@ -35,12 +39,24 @@ async fn main() -> anyhow::Result<()> {
     pretty_env_logger::init();
 
     let db = Db::connect().await?;
+    if let Err(err) = ProblemRepo::mark_legacy_samples(&db).await {
+        log::error!("Error marking sample test cases: {}", err);
+    }
+    if let Err(err) = ProblemRepo::set_missing_statuses(&db).await {
+        log::error!("Error setting problem statuses: {}", err);
+    }
+    if let Err(err) = ProblemRepo::create_indexes(&db).await {
+        log::error!("Error creating problem indexes: {}", err);
+    }
     let app_state = AppState::default();
 
     let app = Router::<AppState>::new()
         .nest("/auth", auth_routes())
         .nest("/room", room_routes())
         .nest("/problem", problem_routes())
+        .nest("/submission", submission_routes())
+        .nest("/match", match_routes())
+        .nest("/rating", rating_routes())
         .layer(Extension(db))
         .layer(create_cors_layer()?)
         .with_state(app_state);
@@ -0,0 +1,137 @@
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Uuid},
+    options::FindOptions,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ConvertResult, RouteErr},
+    models::problem::Problem,
+    mongo::{oid_as_string, Db},
+    rooms::standings::{Scoring, Standing},
+};
+
+/// A round played in a room, kept once it ends.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Match {
+    #[serde(rename(deserialize = "_id"), serialize_with = "oid_as_string")]
+    pub id: ObjectId,
+    pub room_id: Uuid,
+    pub room_name: String,
+    pub round: u32,
+    pub scoring: Scoring,
+    pub problems: Vec<MatchProblem>,
+    /// Everyone in the room when the round ended, best placed first.
+    pub standings: Vec<Standing>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub duration_secs: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchProblem {
+    #[serde(serialize_with = "oid_as_string")]
+    pub id: ObjectId,
+    pub title: String,
+    pub difficulty: u8,
+}
+
+impl From<&Problem> for MatchProblem {
+    fn from(problem: &Problem) -> Self {
+        Self {
+            id: problem.id,
+            title: problem.title.clone(),
+            difficulty: problem.difficulty,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMatch {
+    pub room_id: Uuid,
+    pub room_name: String,
+    pub round: u32,
+    pub scoring: Scoring,
+    pub problems: Vec<MatchProblem>,
+    pub standings: Vec<Standing>,
+    /// Of everyone in `standings`, stored as ObjectIds so a user's matches
+    /// can be looked up.
+    pub user_ids: Vec<ObjectId>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub duration_secs: u32,
+}
+
+#[derive(Clone)]
+pub struct MatchRepo(Db);
+
+impl MatchRepo {
+    pub async fn create(&self, data: &CreateMatch) -> Result<ObjectId, RouteErr> {
+        self.0
+            .collection::<CreateMatch>("matches")
+            .insert_one(data, None)
+            .await
+            .convert(Some("Error saving match."))
+            .map(|res| res.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Matches a user played in, newest first, starting after `cursor`.
+    pub async fn get_for_user(
+        &self,
+        user_id: &ObjectId,
+        cursor: Option<ObjectId>,
+    ) -> Result<Vec<Match>, RouteErr> {
+        let mut filter = doc! { "userIds": user_id };
+        if let Some(cursor) = cursor {
+            filter.insert("_id", doc! { "$lt": cursor });
+        }
+
+        let cursor = self
+            .0
+            .collection::<Match>("matches")
+            .find(
+                filter,
+                Some(
+                    FindOptions::builder()
+                        .sort(doc! { "_id": -1 })
+                        .limit(20)
+                        .build(),
+                ),
+            )
+            .await
+            .convert(Some("Error fetching matches."))?;
+
+        cursor
+            .try_collect::<Vec<_>>()
+            .await
+            .convert(Some("Error fetching matches."))
+    }
+
+    pub async fn get_by_id(&self, id: &ObjectId) -> Result<Match, RouteErr> {
+        self.0
+            .collection::<Match>("matches")
+            .find_one(doc! { "_id": id }, None)
+            .await
+            .convert(Some("Error fetching match."))?
+            .convert(Some("Match not found."))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MatchRepo
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let db = parts.extensions.get::<Db>().unwrap();
+        Ok(Self(db.clone()))
+    }
+}
@@ -1,2 +1,5 @@
+pub mod matches;
 pub mod problem;
+pub mod rating;
+pub mod submission;
 pub mod user;
@@ -1,15 +1,27 @@
 use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
 use futures::stream::{StreamExt, TryStreamExt};
 use mongodb::{
-    bson::{doc, oid::ObjectId, to_document, Bson},
-    options::FindOptions,
+    bson::{doc, oid::ObjectId, to_bson, to_document, Bson, Document},
+    options::{FindOptions, IndexOptions},
+    IndexModel,
 };
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::RangeInclusive,
+};
 
 use crate::{
     errors::{ConvertResult, RouteErr},
-    mongo::{oid_as_string, Db},
+    mongo::{oid_as_string, Db, ToObjectId},
+    rooms::{
+        judge::{
+            self, checker::Checker, IoMode, JudgingResults, Limits, Settings, Verdict,
+            DEFAULT_MEMORY_LIMIT_KB, DEFAULT_TIME_LIMIT_MS,
+        },
+        languages::{self, Language, LANGUAGES},
+    },
     routers::rooms::ProblemsFilter,
 };
 
@@ -20,6 +32,45 @@ use super::user::PublicUser;
 pub struct TestCase {
     pub input: String,
     pub output: String,
+    /// Shown to everyone as an example. Test cases that aren't samples are
+    /// hidden, and only used for judging.
+    #[serde(default)]
+    pub sample: bool,
+    /// Index into the problem's subtasks.
+    #[serde(default)]
+    pub subtask: usize,
+}
+
+/// A group of test cases whose points are earned only if all of them pass.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Subtask {
+    pub name: String,
+    pub points: u32,
+}
+
+/// What a problem without subtasks is worth, all or nothing.
+pub const DEFAULT_POINTS: u32 = 100;
+
+/// Where a problem is in being written. Only published problems are listed,
+/// searched and picked for rooms.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProblemStatus {
+    #[default]
+    Draft,
+    InReview,
+    Published,
+    /// No longer listed, but still around for old submissions and matches.
+    Archived,
+}
+
+/// The author's own solution, which has to pass every test case for the
+/// problem to be published.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Solution {
+    #[serde(with = "languages::by_name")]
+    pub language: &'static Language,
+    pub code: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -33,6 +84,29 @@ pub struct Problem {
     pub boilerplate_code: Code,
     pub test_cases: Vec<TestCase>,
     pub difficulty: u8,
+    #[serde(default = "default_time_limit_ms")]
+    pub time_limit_ms: u32,
+    #[serde(default = "default_memory_limit_kb")]
+    pub memory_limit_kb: u32,
+    /// If unset, `Checker::default_for` the I/O mode.
+    #[serde(default)]
+    pub checker: Option<Checker>,
+    #[serde(default)]
+    pub io_mode: IoMode,
+    /// If empty, the whole problem is one subtask worth `DEFAULT_POINTS`.
+    #[serde(default)]
+    pub subtasks: Vec<Subtask>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub status: ProblemStatus,
+    /// Only seen by the author.
+    #[serde(default)]
+    pub solution: Option<Solution>,
+    /// Goes up with every edit, so the problem can be published only if it is
+    /// still what was checked.
+    #[serde(default)]
+    pub revision: u32,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -46,6 +120,13 @@ pub struct PublicProblem {
     pub boilerplate_code: Code,
     pub default_test_cases: Vec<TestCase>,
     pub difficulty: u8,
+    pub time_limit_ms: u32,
+    pub memory_limit_kb: u32,
+    pub io_mode: IoMode,
+    pub subtasks: Vec<Subtask>,
+    /// The most points the problem is worth.
+    pub points: u32,
+    pub tags: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -57,14 +138,204 @@ pub struct ListingProblem {
     pub author: PublicUser,
     pub description: String,
     pub difficulty: u8,
-    pub draft: Option<bool>,
+    #[serde(default)]
+    pub status: ProblemStatus,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+const SEARCH_PAGE_SIZE: u32 = 20;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub enum SearchSort {
+    /// How well the problem matches the text query.
+    Relevance,
+    Newest,
+    Oldest,
+    Easiest,
+    Hardest,
+    Title,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+impl SearchSort {
+    /// Ties are broken by `_id`, so every problem has its own place.
+    fn order(self) -> Document {
+        match self {
+            SearchSort::Relevance => doc! { "score": -1, "_id": -1 },
+            SearchSort::Newest => doc! { "_id": -1 },
+            SearchSort::Oldest => doc! { "_id": 1 },
+            SearchSort::Easiest => doc! { "difficulty": 1, "_id": -1 },
+            SearchSort::Hardest => doc! { "difficulty": -1, "_id": -1 },
+            SearchSort::Title => doc! { "title": 1, "_id": -1 },
+        }
+    }
+
+    /// Where the page after `searched` starts: its id, then what it was
+    /// sorted by.
+    fn cursor(self, searched: &SearchedProblem) -> String {
+        let id = searched.problem.id;
+        match self {
+            SearchSort::Relevance => format!("{}:{}", id, searched.score),
+            SearchSort::Newest | SearchSort::Oldest => id.to_string(),
+            SearchSort::Easiest | SearchSort::Hardest => {
+                format!("{}:{}", id, searched.problem.difficulty)
+            }
+            SearchSort::Title => format!("{}:{}", id, searched.problem.title),
+        }
+    }
+
+    /// Matches the problems sorted after `cursor`.
+    fn after(self, cursor: &str) -> Result<Document, RouteErr> {
+        let invalid = || RouteErr::Msg(StatusCode::BAD_REQUEST, "Invalid cursor.".into());
+        let (id, key) = cursor.split_once(':').unwrap_or((cursor, ""));
+        let id = ObjectId::parse_str(id).map_err(|_| invalid())?;
+
+        let (field, value, direction) = match self {
+            SearchSort::Newest => return Ok(doc! { "_id": { "$lt": id } }),
+            SearchSort::Oldest => return Ok(doc! { "_id": { "$gt": id } }),
+            SearchSort::Relevance => (
+                "score",
+                Bson::Double(key.parse().map_err(|_| invalid())?),
+                "$lt",
+            ),
+            SearchSort::Easiest | SearchSort::Hardest => (
+                "difficulty",
+                Bson::Int32(key.parse::<u8>().map_err(|_| invalid())? as i32),
+                if matches!(self, SearchSort::Easiest) {
+                    "$gt"
+                } else {
+                    "$lt"
+                },
+            ),
+            SearchSort::Title => ("title", Bson::String(key.to_string()), "$gt"),
+        };
+
+        Ok(doc! {
+            "$or": [
+                { field: { direction: &value } },
+                { field: &value, "_id": { "$lt": id } },
+            ]
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct Code {
-    pub python: String,
-    pub javascript: String,
+pub struct SearchProblems {
+    /// Searched for in titles, descriptions and tags.
+    #[serde(default)]
+    pub query: String,
+    /// Comma separated. Problems must have all of them.
+    pub tags: Option<String>,
+    pub min_difficulty: Option<u8>,
+    pub max_difficulty: Option<u8>,
+    /// Id of the author.
+    pub author: Option<String>,
+    /// By relevance if there's a query, otherwise newest first.
+    pub sort: Option<SearchSort>,
+    /// From the previous page's results, searched with the same sort.
+    pub cursor: Option<String>,
+}
+
+impl SearchProblems {
+    fn tags(&self) -> Vec<String> {
+        self.tags
+            .as_deref()
+            .map(|tags| normalize_tags(tags.split(',')))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct FacetCount<T> {
+    #[serde(rename(deserialize = "_id"))]
+    pub value: T,
+    pub count: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct Total {
+    count: u32,
+}
+
+/// A problem in a page of search results, with what it was sorted by.
+#[derive(Deserialize, Debug)]
+struct SearchedProblem {
+    #[serde(flatten)]
+    problem: ListingProblem,
+    /// The text score, when sorting by relevance.
+    #[serde(default)]
+    score: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchFacets {
+    problems: Vec<SearchedProblem>,
+    tags: Vec<FacetCount<String>>,
+    difficulties: Vec<FacetCount<u8>>,
+    total: Vec<Total>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults {
+    pub problems: Vec<ListingProblem>,
+    /// Gets the next page, if there is one.
+    pub next_cursor: Option<String>,
+    /// How many problems match, across all pages.
+    pub total: u32,
+    /// How many matching problems have each tag, most first.
+    pub tags: Vec<FacetCount<String>>,
+    /// How many matching problems there are of each difficulty.
+    pub difficulties: Vec<FacetCount<u8>>,
+}
+
+const MAX_TAGS: usize = 10;
+const MAX_TAG_LENGTH: usize = 32;
+
+/// Tags are stored trimmed and lowercase.
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Normalized and without duplicates or empty tags.
+fn normalize_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut normalized = Vec::<String>::new();
+    for tag in tags {
+        let tag = normalize_tag(tag);
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Boilerplate code keyed by language name, see `rooms::languages`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(transparent)]
+pub struct Code(pub BTreeMap<String, String>);
+
+impl Default for Code {
+    fn default() -> Self {
+        Code(
+            LANGUAGES
+                .iter()
+                .map(|language| (language.name.to_string(), String::new()))
+                .collect(),
+        )
+    }
+}
+
+impl Code {
+    pub fn validate(&self) -> Result<(), RouteErr> {
+        match self.0.keys().find(|name| languages::get(name).is_none()) {
+            Some(name) => Err(RouteErr::Msg(
+                StatusCode::BAD_REQUEST,
+                format!("Unsupported language: {}", name),
+            )),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -75,6 +346,237 @@ pub struct UpdateProblem {
     pub test_cases: Vec<TestCase>,
     pub boilerplate_code: Code,
     pub difficulty: i32,
+    #[serde(default = "default_time_limit_ms")]
+    pub time_limit_ms: u32,
+    #[serde(default = "default_memory_limit_kb")]
+    pub memory_limit_kb: u32,
+    #[serde(default)]
+    pub checker: Option<Checker>,
+    #[serde(default)]
+    pub io_mode: IoMode,
+    #[serde(default)]
+    pub subtasks: Vec<Subtask>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub solution: Option<Solution>,
+}
+
+fn default_time_limit_ms() -> u32 {
+    DEFAULT_TIME_LIMIT_MS
+}
+
+fn default_memory_limit_kb() -> u32 {
+    DEFAULT_MEMORY_LIMIT_KB
+}
+
+const TIME_LIMIT_RANGE_MS: RangeInclusive<u32> = 100..=10_000;
+const MEMORY_LIMIT_RANGE_KB: RangeInclusive<u32> = 16 * 1024..=1024 * 1024;
+
+impl Problem {
+    pub fn sample_test_cases(&self) -> Vec<TestCase> {
+        self.test_cases
+            .iter()
+            .filter(|test_case| test_case.sample)
+            .cloned()
+            .collect()
+    }
+
+    /// Whether users other than the author can open the problem.
+    pub fn is_visible_to(&self, user_id: &ObjectId) -> bool {
+        matches!(
+            self.status,
+            ProblemStatus::Published | ProblemStatus::Archived
+        ) || self.author.id == *user_id
+    }
+
+    /// Checks everything needed to publish the problem other than running
+    /// its solution, which is returned.
+    pub fn validate_for_publishing(&self) -> Result<&Solution, RouteErr> {
+        let mut missing = Vec::new();
+        if self.title.trim().is_empty() || self.title == "Untitled" {
+            missing.push("a title");
+        }
+        if self.description.trim().is_empty() {
+            missing.push("a description");
+        }
+        if !self.test_cases.iter().any(|test_case| test_case.sample) {
+            missing.push("sample test cases");
+        }
+        if !self.test_cases.iter().any(|test_case| !test_case.sample) {
+            missing.push("hidden test cases");
+        }
+        match &self.solution {
+            Some(solution) if missing.is_empty() => Ok(solution),
+            solution => {
+                if solution.is_none() {
+                    missing.push("a solution");
+                }
+                Err(RouteErr::Msg(
+                    StatusCode::BAD_REQUEST,
+                    format!("Add {} before publishing.", missing.join(", ")),
+                ))
+            }
+        }
+    }
+
+    pub fn to_public(&self) -> PublicProblem {
+        PublicProblem {
+            id: self.id,
+            title: self.title.clone(),
+            author: self.author.clone(),
+            description: self.description.clone(),
+            boilerplate_code: self.boilerplate_code.clone(),
+            default_test_cases: self.sample_test_cases(),
+            difficulty: self.difficulty,
+            time_limit_ms: self.time_limit_ms,
+            memory_limit_kb: self.memory_limit_kb,
+            io_mode: self.io_mode,
+            subtasks: self.subtasks.clone(),
+            points: self.max_score(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    pub fn max_score(&self) -> u32 {
+        if self.subtasks.is_empty() {
+            return DEFAULT_POINTS;
+        }
+        self.subtasks.iter().map(|subtask| subtask.points).sum()
+    }
+
+    /// The points earned by judged results, from every subtask whose test
+    /// cases all passed.
+    pub fn score(&self, results: &JudgingResults) -> u32 {
+        if self.subtasks.is_empty() {
+            return if results.all_passed() {
+                DEFAULT_POINTS
+            } else {
+                0
+            };
+        }
+
+        let mut passed = vec![true; self.subtasks.len()];
+        let mut has_tests = vec![false; self.subtasks.len()];
+        for (test_case, result) in self.test_cases.iter().zip(&results.results) {
+            if let Some(subtask) = passed.get_mut(test_case.subtask) {
+                *subtask &= matches!(result.verdict, Verdict::Accepted);
+                has_tests[test_case.subtask] = true;
+            }
+        }
+
+        self.subtasks
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| passed[*index] && has_tests[*index])
+            .map(|(_, subtask)| subtask.points)
+            .sum()
+    }
+
+    pub fn judge_settings(&self) -> Settings {
+        Settings {
+            limits: Limits {
+                time_limit_ms: self.time_limit_ms,
+                memory_limit_kb: self.memory_limit_kb,
+            },
+            checker: self
+                .checker
+                .clone()
+                .unwrap_or_else(|| Checker::default_for(self.io_mode)),
+            io_mode: self.io_mode,
+        }
+    }
+}
+
+impl UpdateProblem {
+    fn validate(&self) -> Result<(), RouteErr> {
+        self.boilerplate_code.validate()?;
+
+        // the judge can't run programs for longer than its backend allows
+        let max_time_limit_ms = judge::max_time_limit_ms()
+            .map_or(*TIME_LIMIT_RANGE_MS.end(), |max| {
+                max.min(*TIME_LIMIT_RANGE_MS.end())
+            });
+        if !(*TIME_LIMIT_RANGE_MS.start()..=max_time_limit_ms).contains(&self.time_limit_ms) {
+            return Err(RouteErr::Msg(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Time limit must be between {} and {} milliseconds.",
+                    TIME_LIMIT_RANGE_MS.start(),
+                    max_time_limit_ms
+                ),
+            ));
+        }
+
+        if !MEMORY_LIMIT_RANGE_KB.contains(&self.memory_limit_kb) {
+            return Err(RouteErr::Msg(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Memory limit must be between {} and {} kilobytes.",
+                    MEMORY_LIMIT_RANGE_KB.start(),
+                    MEMORY_LIMIT_RANGE_KB.end()
+                ),
+            ));
+        }
+
+        match &self.checker {
+            Some(Checker::FloatTolerance { epsilon })
+                if !(epsilon.is_finite() && *epsilon >= 0.0) =>
+            {
+                return Err(RouteErr::Msg(
+                    StatusCode::BAD_REQUEST,
+                    "Checker epsilon must be a non-negative number.".into(),
+                ));
+            }
+            Some(Checker::Custom { code, .. }) if code.trim().is_empty() => {
+                return Err(RouteErr::Msg(
+                    StatusCode::BAD_REQUEST,
+                    "Custom checker code is empty.".into(),
+                ));
+            }
+            _ => (),
+        }
+
+        let subtasks = self.subtasks.len().max(1);
+        if self
+            .test_cases
+            .iter()
+            .any(|test_case| test_case.subtask >= subtasks)
+        {
+            return Err(RouteErr::Msg(
+                StatusCode::BAD_REQUEST,
+                "A test case belongs to a subtask that doesn't exist.".into(),
+            ));
+        }
+
+        if self.tags.len() > MAX_TAGS {
+            return Err(RouteErr::Msg(
+                StatusCode::BAD_REQUEST,
+                format!("Problems can have at most {} tags.", MAX_TAGS),
+            ));
+        }
+
+        if self
+            .tags
+            .iter()
+            .any(|tag| tag.trim().chars().count() > MAX_TAG_LENGTH)
+        {
+            return Err(RouteErr::Msg(
+                StatusCode::BAD_REQUEST,
+                format!("Tags can be at most {} characters long.", MAX_TAG_LENGTH),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// How many test cases were shown as examples before they could be marked
+/// as samples.
+const LEGACY_SAMPLES: i32 = 3;
+
+fn is_published() -> Document {
+    doc! { "status": to_bson(&ProblemStatus::Published).unwrap() }
 }
 
 #[derive(Clone)]
@@ -85,20 +587,15 @@ impl ProblemRepo {
         &self,
         cursor: Option<ObjectId>,
     ) -> Result<Vec<ListingProblem>, RouteErr> {
+        let mut filter = is_published();
+        if let Some(id) = cursor {
+            filter.insert("_id", doc! { "$gt": id });
+        }
+
         let cursor = self
             .0
             .collection::<Problem>("problems")
-            .find(
-                cursor.map(|id| {
-                    doc! {
-                        "_id": {
-                            "$gt": id
-                        },
-                        "testCases.4": { "$exists": true }
-                    }
-                }),
-                Some(FindOptions::builder().limit(10).build()),
-            )
+            .find(filter, Some(FindOptions::builder().limit(10).build()))
             .await
             .convert(Some("Error fetching problems."))?;
 
@@ -115,7 +612,8 @@ impl ProblemRepo {
                 description: p.description.clone(),
                 author: p.author.clone(),
                 difficulty: p.difficulty,
-                draft: Some(p.test_cases.len() < 5),
+                status: p.status,
+                tags: p.tags.clone(),
             })
             .collect::<Vec<_>>())
     }
@@ -129,10 +627,11 @@ impl ProblemRepo {
                     "title": "Untitled",
                     "testCases": [],
                     "description": "",
-                    "boilerplateCode": to_document(&Code {
-                        ..Default::default()
-                    }).unwrap(),
+                    "boilerplateCode": to_document(&Code::default()).unwrap(),
                     "difficulty": 0,
+                    "timeLimitMs": DEFAULT_TIME_LIMIT_MS,
+                    "memoryLimitKb": DEFAULT_MEMORY_LIMIT_KB,
+                    "status": to_bson(&ProblemStatus::Draft).unwrap(),
                 },
                 None,
             )
@@ -154,12 +653,16 @@ impl ProblemRepo {
             .convert(Some("Error fetching problem."))
     }
 
+    /// Published problems and those in review go back to being drafts, so
+    /// they're checked again before being published.
     pub async fn update(
         &self,
         problem_id: &ObjectId,
         user_id: &ObjectId,
         data: &UpdateProblem,
     ) -> Result<(), RouteErr> {
+        data.validate()?;
+
         let test_cases = data
             .test_cases
             .iter()
@@ -167,6 +670,8 @@ impl ProblemRepo {
                 doc! {
                     "input": t.input.clone(),
                     "output": t.output.clone(),
+                    "sample": t.sample,
+                    "subtask": t.subtask as i64,
                 }
             })
             .collect::<Vec<_>>();
@@ -184,12 +689,17 @@ impl ProblemRepo {
                         "difficulty": &data.difficulty,
                         "title": &data.title,
                         "description": &data.description,
-                        "boilerplateCode": {
-                            "javascript": &data.boilerplate_code.javascript,
-                            "python": &data.boilerplate_code.python,
-                        },
+                        "boilerplateCode": to_document(&data.boilerplate_code).unwrap(),
                         "testCases": &test_cases,
-                    }
+                        "timeLimitMs": data.time_limit_ms,
+                        "memoryLimitKb": data.memory_limit_kb,
+                        "checker": to_bson(&data.checker).unwrap(),
+                        "ioMode": to_bson(&data.io_mode).unwrap(),
+                        "subtasks": to_bson(&data.subtasks).unwrap(),
+                        "tags": normalize_tags(data.tags.iter().map(String::as_str)),
+                        "solution": to_bson(&data.solution).unwrap(),
+                    },
+                    "$inc": { "revision": 1 },
                 },
                 None,
             )
@@ -203,91 +713,406 @@ impl ProblemRepo {
             ));
         }
 
+        self.0
+            .collection::<Problem>("problems")
+            .update_one(
+                doc! {
+                    "_id": problem_id,
+                    "status": {
+                        "$in": [
+                            to_bson(&ProblemStatus::Published).unwrap(),
+                            to_bson(&ProblemStatus::InReview).unwrap(),
+                        ]
+                    }
+                },
+                doc! { "$set": { "status": to_bson(&ProblemStatus::Draft).unwrap() } },
+                None,
+            )
+            .await
+            .convert(Some("Error updating problem."))?;
+
         Ok(())
     }
 
-    pub async fn search(&self, what: &String) -> Result<Vec<ListingProblem>, RouteErr> {
-        if what == "" {
-            let cursor = self
-                .0
-                .collection::<ListingProblem>("problems")
-                .find(
-                    doc! {
-                        "testCases.4": { "$exists": true }
-                    },
-                    Some(FindOptions::builder().limit(10).build()),
-                )
-                .await
-                .convert(Some("Error fetching problems."))?;
+    /// One page of the problems matching `search`, with how many there are of
+    /// each tag and difficulty among all of them.
+    pub async fn search(&self, search: &SearchProblems) -> Result<SearchResults, RouteErr> {
+        let mut query = is_published();
+        let text = search.query.trim();
+        if !text.is_empty() {
+            query.insert("$text", doc! { "$search": text });
+        }
+        let tags = search.tags();
+        if !tags.is_empty() {
+            query.insert("tags", doc! { "$all": tags });
+        }
+        if search.min_difficulty.is_some() || search.max_difficulty.is_some() {
+            query.insert(
+                "difficulty",
+                doc! {
+                    "$gte": search.min_difficulty.unwrap_or(u8::MIN) as i32,
+                    "$lte": search.max_difficulty.unwrap_or(u8::MAX) as i32,
+                },
+            );
+        }
+        if let Some(author) = &search.author {
+            // authors are stored with their id as a string
+            query.insert("author.id", author.to_object_id()?.to_string());
+        }
 
-            let problems = cursor
-                .try_collect::<Vec<_>>()
-                .await
-                .convert(Some("Error fetching problems."))?;
+        let sort = match search.sort {
+            // there's no text score without a text query
+            Some(SearchSort::Relevance) | None if text.is_empty() => SearchSort::Newest,
+            Some(sort) => sort,
+            None => SearchSort::Relevance,
+        };
+        let mut page = Vec::new();
+        if let Some(cursor) = &search.cursor {
+            page.push(doc! { "$match": sort.after(cursor)? });
+        }
+        // one more than fits, to tell if there's another page
+        page.push(doc! { "$limit": SEARCH_PAGE_SIZE as i64 + 1 });
 
-            return Ok(problems);
+        let mut pipeline = vec![doc! { "$match": query }];
+        if matches!(sort, SearchSort::Relevance) {
+            pipeline.push(doc! { "$addFields": { "score": { "$meta": "textScore" } } });
         }
+        pipeline.extend([
+            doc! { "$sort": sort.order() },
+            doc! {
+                "$facet": {
+                    "problems": page,
+                    "tags": [
+                        { "$unwind": "$tags" },
+                        { "$sortByCount": "$tags" },
+                    ],
+                    "difficulties": [
+                        { "$group": { "_id": "$difficulty", "count": { "$sum": 1 } } },
+                        { "$sort": { "_id": 1 } },
+                    ],
+                    "total": [
+                        { "$count": "count" },
+                    ],
+                }
+            },
+        ]);
 
-        let cursor = self
+        let mut cursor = self
             .0
-            .collection::<ListingProblem>("problems")
-            .find(
+            .collection::<Problem>("problems")
+            .aggregate(pipeline, None)
+            .await
+            .convert(Some("Error searching problems."))?
+            .with_type::<SearchFacets>();
+
+        let facets = cursor
+            .try_next()
+            .await
+            .convert(Some("Error searching problems."))?
+            .convert(Some("Error searching problems."))?;
+        let total = facets.total.first().map_or(0, |total| total.count);
+
+        let mut problems = facets.problems;
+        let next_cursor = if problems.len() > SEARCH_PAGE_SIZE as usize {
+            problems.truncate(SEARCH_PAGE_SIZE as usize);
+            problems.last().map(|last| sort.cursor(last))
+        } else {
+            None
+        };
+
+        Ok(SearchResults {
+            next_cursor,
+            problems: problems
+                .into_iter()
+                .map(|searched| searched.problem)
+                .collect(),
+            total,
+            tags: facets.tags,
+            difficulties: facets.difficulties,
+        })
+    }
+
+    /// Sets the status of one of the user's problems.
+    /// Publishes a problem, unless it has been edited since `revision`.
+    pub async fn publish(
+        &self,
+        problem_id: &ObjectId,
+        user_id: &ObjectId,
+        revision: u32,
+    ) -> Result<(), RouteErr> {
+        // problems that were never edited don't have a revision yet
+        let revision = match revision {
+            0 => doc! { "$in": [0, null] },
+            revision => doc! { "$eq": revision },
+        };
+        let res = self
+            .0
+            .collection::<Problem>("problems")
+            .update_one(
                 doc! {
-                    "$text": {
-                        "$search": what
-                    },
-                    "testCases.4": { "$exists": true }
+                    "_id": problem_id,
+                    "author.id": user_id.to_string(),
+                    "revision": revision,
                 },
-                Some(FindOptions::builder().limit(10).build()),
+                doc! {
+                    "$set": {
+                        "status": to_bson(&ProblemStatus::Published).unwrap(),
+                    }
+                },
+                None,
             )
             .await
-            .convert(Some("Error fetching problems."))?;
+            .convert(Some("Error updating problem."))?;
 
-        let problems = cursor
-            .try_collect::<Vec<_>>()
+        if res.matched_count == 0 {
+            return Err(RouteErr::Msg(
+                StatusCode::CONFLICT,
+                "The problem was edited while its solution was being checked. Publish it again."
+                    .into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_status(
+        &self,
+        problem_id: &ObjectId,
+        user_id: &ObjectId,
+        status: ProblemStatus,
+    ) -> Result<(), RouteErr> {
+        let res = self
+            .0
+            .collection::<Problem>("problems")
+            .update_one(
+                doc! {
+                    "_id": problem_id,
+                    "author.id": user_id.to_string()
+                },
+                doc! {
+                    "$set": {
+                        "status": to_bson(&status).unwrap(),
+                    }
+                },
+                None,
+            )
             .await
-            .convert(Some("Error fetching problems."))?;
+            .convert(Some("Error updating problem."))?;
 
-        Ok(problems)
+        if res.matched_count == 0 {
+            return Err(RouteErr::Msg(
+                StatusCode::UNAUTHORIZED,
+                "Unauthorized update of problem.".into(),
+            ));
+        }
+
+        Ok(())
     }
 
+    /// Marks the first `LEGACY_SAMPLES` test cases of problems from before
+    /// samples existed as samples, since those were the ones shown.
+    pub async fn mark_legacy_samples(db: &Db) -> anyhow::Result<()> {
+        db.collection::<Problem>("problems")
+            .update_many(
+                doc! { "testCases.sample": { "$exists": false } },
+                vec![doc! {
+                    "$set": {
+                        "testCases": {
+                            "$map": {
+                                "input": { "$range": [0, { "$size": "$testCases" }] },
+                                "as": "index",
+                                "in": {
+                                    "$mergeObjects": [
+                                        { "$arrayElemAt": ["$testCases", "$$index"] },
+                                        { "sample": { "$lt": ["$$index", LEGACY_SAMPLES] } },
+                                    ]
+                                },
+                            }
+                        }
+                    }
+                }],
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Gives problems from before statuses existed one: published if they
+    /// have at least 5 test cases, which used to be enough to be listed,
+    /// otherwise draft.
+    pub async fn set_missing_statuses(db: &Db) -> anyhow::Result<()> {
+        let problems = db.collection::<Problem>("problems");
+        problems
+            .update_many(
+                doc! {
+                    "status": { "$exists": false },
+                    "testCases.4": { "$exists": true },
+                },
+                doc! { "$set": is_published() },
+                None,
+            )
+            .await?;
+        problems
+            .update_many(
+                doc! { "status": { "$exists": false } },
+                doc! { "$set": { "status": to_bson(&ProblemStatus::Draft)? } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Creates the indexes that searching and filtering problems use, if
+    /// they don't exist. A collection can only have one text index, so an
+    /// older one not named `search` has to be dropped first.
+    /// Each index is created on its own, so one failing doesn't stop the rest.
+    pub async fn create_indexes(db: &Db) -> anyhow::Result<()> {
+        let problems = db.collection::<Problem>("problems");
+
+        let mut existing = problems.list_indexes(None).await?;
+        while let Some(index) = existing.try_next().await? {
+            let name = index.options.and_then(|options| options.name);
+            if index.keys.get_str("_fts") == Ok("text") && name.as_deref() != Some("search") {
+                if let Some(name) = name {
+                    problems.drop_index(name, None).await?;
+                }
+            }
+        }
+
+        for index in [
+            IndexModel::builder()
+                .keys(doc! { "title": "text", "description": "text", "tags": "text" })
+                .options(IndexOptions::builder().name("search".to_string()).build())
+                .build(),
+            IndexModel::builder().keys(doc! { "status": 1 }).build(),
+            IndexModel::builder().keys(doc! { "tags": 1 }).build(),
+            IndexModel::builder().keys(doc! { "difficulty": 1 }).build(),
+            IndexModel::builder().keys(doc! { "author.id": 1 }).build(),
+        ] {
+            let keys = index.keys.clone();
+            if let Err(err) = problems.create_index(index, None).await {
+                log::error!("Error creating problems index {}: {}", keys, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the filters in order into a list of problems without
+    /// duplicates. `solved` is for `ProblemsFilter::Unsolved`. Fails if any
+    /// filter matches no problems.
     pub async fn get_from_filters(
         &self,
-        filters: &Vec<ProblemsFilter>,
+        filters: &[ProblemsFilter],
+        solved: &[ObjectId],
     ) -> Result<Vec<Problem>, RouteErr> {
-        let problem_id_filters = filters
+        let single_ids = filters
             .iter()
-            .filter(|f| match f {
-                ProblemsFilter::Single { .. } => true,
-            })
-            .map(|f| match f {
-                ProblemsFilter::Single { id } => ObjectId::parse_str(id),
+            .filter_map(|f| match f {
+                ProblemsFilter::Single { id } => Some(ObjectId::parse_str(id)),
+                _ => None,
             })
             .collect::<Result<Vec<_>, mongodb::bson::oid::Error>>()
             .map_err(|_| RouteErr::Msg(StatusCode::BAD_REQUEST, "Invalid ObjectId".into()))?;
 
-        log::info!("{:?}", problem_id_filters);
+        let mut filter = is_published();
+        filter.insert("_id", doc! { "$in": &single_ids });
 
         let cursor = self
             .0
             .collection::<Problem>("problems")
-            .find(
-                doc! {
-                    "_id": {
-                        "$in": problem_id_filters
+            .find(filter, None)
+            .await
+            .convert(Some("Error fetching problems."))?;
+
+        let mut singles = cursor
+            .map_ok(|problem| (problem.id, problem))
+            .try_collect::<HashMap<_, _>>()
+            .await
+            .convert(Some("Error fetching problems."))?;
+
+        let mut single_ids = single_ids.into_iter();
+        let mut problems = Vec::<Problem>::new();
+        let mut unmatched = Vec::new();
+        for (index, filter) in filters.iter().enumerate() {
+            let (query, count) = match filter {
+                ProblemsFilter::Single { .. } => {
+                    let id = single_ids.next().expect("an id for each single filter");
+                    match singles.remove(&id) {
+                        _ if problems.iter().any(|chosen| chosen.id == id) => {}
+                        Some(problem) => problems.push(problem),
+                        None => unmatched.push(format!("#{} ({})", index + 1, filter)),
                     }
-                },
+                    continue;
+                }
+                ProblemsFilter::Random { count } => (doc! {}, count),
+                ProblemsFilter::DifficultyRange { min, max, count } => (
+                    doc! { "difficulty": { "$gte": *min as i32, "$lte": *max as i32 } },
+                    count,
+                ),
+                ProblemsFilter::ByTag { tag, count } => {
+                    (doc! { "tags": normalize_tag(tag) }, count)
+                }
+                ProblemsFilter::ByAuthor { author_id, count } => (
+                    // authors are stored with their id as a string
+                    doc! { "author.id": author_id.to_object_id()?.to_string() },
+                    count,
+                ),
+                ProblemsFilter::Unsolved { count } => (doc! { "_id": { "$nin": solved } }, count),
+            };
+
+            let chosen = problems
+                .iter()
+                .map(|problem| problem.id)
+                .collect::<Vec<_>>();
+            let sampled = self.sample(query, &chosen, *count).await?;
+            if sampled.is_empty() {
+                unmatched.push(format!("#{} ({})", index + 1, filter));
+            }
+            problems.extend(sampled);
+        }
+
+        if !unmatched.is_empty() {
+            return Err(RouteErr::Msg(
+                StatusCode::BAD_REQUEST,
+                format!("No problems found for filters {}.", unmatched.join(", ")),
+            ));
+        }
+
+        Ok(problems)
+    }
+
+    /// Up to `count` random published problems that match `query`, other than
+    /// those in `exclude`.
+    async fn sample(
+        &self,
+        mut query: Document,
+        exclude: &[ObjectId],
+        count: u32,
+    ) -> Result<Vec<Problem>, RouteErr> {
+        query.extend(is_published());
+        query.insert("$and", vec![doc! { "_id": { "$nin": exclude } }]);
+
+        let cursor = self
+            .0
+            .collection::<Problem>("problems")
+            .aggregate(
+                [
+                    doc! { "$match": query },
+                    doc! { "$sample": { "size": count as i64 } },
+                ],
                 None,
             )
             .await
             .convert(Some("Error fetching problems."))?;
 
-        let problems = cursor
+        cursor
+            .with_type::<Problem>()
             .try_collect::<Vec<_>>()
             .await
-            .convert(Some("Error fetching problems."))?;
-
-        Ok(problems)
+            .convert(Some("Error fetching problems."))
     }
 }
 
@@ -0,0 +1,267 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::FindOptions,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ConvertResult, RouteErr},
+    mongo::{oid_as_string, Db},
+    rooms::standings::Standing,
+};
+
+/// What users start with before their first rated match.
+pub const INITIAL_RATING: i32 = 1500;
+/// The most a rating can change in one match.
+const K_FACTOR: f64 = 32.0;
+
+/// New ratings for users who placed `(rating, rank)` in a match, in the same
+/// order. Every pair of users is scored as a game of Elo, won by whoever
+/// placed higher, and the results are averaged.
+pub fn rate(placements: &[(i32, u32)]) -> Vec<i32> {
+    if placements.len() < 2 {
+        return placements.iter().map(|(rating, _)| *rating).collect();
+    }
+
+    let opponents = (placements.len() - 1) as f64;
+    placements
+        .iter()
+        .enumerate()
+        .map(|(index, &(rating, rank))| {
+            let (expected, actual) = placements
+                .iter()
+                .enumerate()
+                .filter(|(other_index, _)| *other_index != index)
+                .fold(
+                    (0.0, 0.0),
+                    |(expected, actual), (_, &(other_rating, other_rank))| {
+                        let won = match rank.cmp(&other_rank) {
+                            Ordering::Less => 1.0,
+                            Ordering::Equal => 0.5,
+                            Ordering::Greater => 0.0,
+                        };
+                        (
+                            expected
+                                + 1.0 / (1.0 + 10f64.powf((other_rating - rating) as f64 / 400.0)),
+                            actual + won,
+                        )
+                    },
+                );
+
+            rating + (K_FACTOR * (actual - expected) / opponents).round() as i32
+        })
+        .collect()
+}
+
+/// A change to a user's rating from a match.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingChange {
+    #[serde(rename(deserialize = "_id"), serialize_with = "oid_as_string")]
+    pub id: ObjectId,
+    #[serde(serialize_with = "oid_as_string")]
+    pub user_id: ObjectId,
+    #[serde(serialize_with = "oid_as_string")]
+    pub match_id: ObjectId,
+    pub rank: u32,
+    pub participants: u32,
+    pub before: i32,
+    pub after: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateRatingChange {
+    pub user_id: ObjectId,
+    pub match_id: ObjectId,
+    pub rank: u32,
+    pub participants: u32,
+    pub before: i32,
+    pub after: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LeaderboardEntry {
+    #[serde(rename(deserialize = "_id"), serialize_with = "oid_as_string")]
+    pub id: ObjectId,
+    pub name: String,
+    pub image: String,
+    pub rating: i32,
+}
+
+#[derive(Clone)]
+pub struct RatingRepo(Db);
+
+impl RatingRepo {
+    /// Rates the users in a match's standings who submitted anything, so
+    /// users who only watched aren't affected.
+    pub async fn rate_match(
+        &self,
+        match_id: &ObjectId,
+        standings: &[Standing],
+    ) -> Result<(), RouteErr> {
+        let rated = standings
+            .iter()
+            .filter(|standing| standing.problems.iter().any(|p| p.attempts > 0))
+            .collect::<Vec<_>>();
+        if rated.len() < 2 {
+            return Ok(());
+        }
+
+        let ratings = self
+            .get_ratings(
+                &rated
+                    .iter()
+                    .map(|standing| standing.user.id)
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+        let placements = rated
+            .iter()
+            .map(|standing| {
+                let rating = ratings
+                    .get(&standing.user.id)
+                    .copied()
+                    .unwrap_or(INITIAL_RATING);
+                (rating, standing.rank)
+            })
+            .collect::<Vec<_>>();
+        let new_ratings = rate(&placements);
+
+        let now = Utc::now();
+        for ((standing, (before, rank)), after) in rated.iter().zip(placements).zip(new_ratings) {
+            self.0
+                .collection::<LeaderboardEntry>("users")
+                .update_one(
+                    doc! { "_id": standing.user.id },
+                    // applied as a change so a match rated at the same time
+                    // isn't overwritten
+                    vec![doc! {
+                        "$set": {
+                            "rating": {
+                                "$add": [{ "$ifNull": ["$rating", INITIAL_RATING] }, after - before],
+                            },
+                        },
+                    }],
+                    None,
+                )
+                .await
+                .convert(Some("Error updating rating."))?;
+
+            self.0
+                .collection::<CreateRatingChange>("ratings")
+                .insert_one(
+                    CreateRatingChange {
+                        user_id: standing.user.id,
+                        match_id: *match_id,
+                        rank,
+                        participants: rated.len() as u32,
+                        before,
+                        after,
+                        created_at: now,
+                    },
+                    None,
+                )
+                .await
+                .convert(Some("Error saving rating change."))?;
+        }
+
+        Ok(())
+    }
+
+    /// Ratings of the users who have one.
+    async fn get_ratings(&self, user_ids: &[ObjectId]) -> Result<HashMap<ObjectId, i32>, RouteErr> {
+        let cursor = self
+            .0
+            .collection::<LeaderboardEntry>("users")
+            .find(
+                doc! {
+                    "_id": { "$in": user_ids },
+                    "rating": { "$type": "number" },
+                },
+                Some(
+                    FindOptions::builder()
+                        .projection(doc! { "name": 1, "image": 1, "rating": 1 })
+                        .build(),
+                ),
+            )
+            .await
+            .convert(Some("Error fetching ratings."))?;
+
+        let users = cursor
+            .try_collect::<Vec<_>>()
+            .await
+            .convert(Some("Error fetching ratings."))?;
+        Ok(users
+            .into_iter()
+            .map(|user| (user.id, user.rating))
+            .collect())
+    }
+
+    /// The highest rated users, best first.
+    pub async fn get_leaderboard(&self) -> Result<Vec<LeaderboardEntry>, RouteErr> {
+        let cursor = self
+            .0
+            .collection::<LeaderboardEntry>("users")
+            .find(
+                doc! { "rating": { "$type": "number" } },
+                Some(
+                    FindOptions::builder()
+                        .projection(doc! { "name": 1, "image": 1, "rating": 1 })
+                        .sort(doc! { "rating": -1, "_id": 1 })
+                        .limit(100)
+                        .build(),
+                ),
+            )
+            .await
+            .convert(Some("Error fetching leaderboard."))?;
+
+        cursor
+            .try_collect::<Vec<_>>()
+            .await
+            .convert(Some("Error fetching leaderboard."))
+    }
+
+    /// A user's rating changes, newest first.
+    pub async fn get_history(&self, user_id: &ObjectId) -> Result<Vec<RatingChange>, RouteErr> {
+        let cursor = self
+            .0
+            .collection::<RatingChange>("ratings")
+            .find(
+                doc! { "userId": user_id },
+                Some(
+                    FindOptions::builder()
+                        .sort(doc! { "_id": -1 })
+                        .limit(100)
+                        .build(),
+                ),
+            )
+            .await
+            .convert(Some("Error fetching rating history."))?;
+
+        cursor
+            .try_collect::<Vec<_>>()
+            .await
+            .convert(Some("Error fetching rating history."))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RatingRepo
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let db = parts.extensions.get::<Db>().unwrap();
+        Ok(Self(db.clone()))
+    }
+}
@@ -0,0 +1,179 @@
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Uuid},
+    options::FindOptions,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ConvertResult, RouteErr},
+    models::problem::Problem,
+    mongo::{oid_as_string, Db},
+    rooms::{
+        judge::{JudgingResults, TestCaseResult},
+        languages::Language,
+    },
+};
+
+/// A judged submission to a problem, kept so attempts can be looked back on.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Submission {
+    #[serde(rename(deserialize = "_id"), serialize_with = "oid_as_string")]
+    pub id: ObjectId,
+    #[serde(serialize_with = "oid_as_string")]
+    pub user_id: ObjectId,
+    #[serde(serialize_with = "oid_as_string")]
+    pub problem_id: ObjectId,
+    /// The room it was submitted in.
+    pub room_id: Option<Uuid>,
+    pub language: String,
+    pub code: String,
+    pub results: Vec<TestCaseResult>,
+    pub passed: bool,
+    /// Points earned from the problem's subtasks.
+    #[serde(default)]
+    pub score: u32,
+    pub runtime: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateSubmission {
+    pub user_id: ObjectId,
+    pub problem_id: ObjectId,
+    pub room_id: Option<Uuid>,
+    pub language: String,
+    pub code: String,
+    pub results: Vec<TestCaseResult>,
+    pub passed: bool,
+    pub score: u32,
+    pub runtime: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CreateSubmission {
+    pub fn new(
+        user_id: ObjectId,
+        problem: &Problem,
+        room_id: Option<Uuid>,
+        language: &Language,
+        code: String,
+        results: &JudgingResults,
+    ) -> Self {
+        Self {
+            user_id,
+            problem_id: problem.id,
+            room_id,
+            language: language.name.to_string(),
+            code,
+            // only what the submitter was shown
+            results: results.visible(&problem.test_cases),
+            passed: results.all_passed(),
+            score: problem.score(results),
+            runtime: results.runtime,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SubmissionRepo(Db);
+
+impl SubmissionRepo {
+    pub async fn create(&self, data: &CreateSubmission) -> Result<ObjectId, RouteErr> {
+        self.0
+            .collection::<CreateSubmission>("submissions")
+            .insert_one(data, None)
+            .await
+            .convert(Some("Error saving submission."))
+            .map(|res| res.inserted_id.as_object_id().unwrap())
+    }
+
+    /// A user's submissions to a problem, newest first.
+    pub async fn get_for_problem(
+        &self,
+        user_id: &ObjectId,
+        problem_id: &ObjectId,
+    ) -> Result<Vec<Submission>, RouteErr> {
+        let cursor = self
+            .0
+            .collection::<Submission>("submissions")
+            .find(
+                doc! {
+                    "userId": user_id,
+                    "problemId": problem_id,
+                },
+                Some(
+                    FindOptions::builder()
+                        .sort(doc! { "createdAt": -1 })
+                        .limit(50)
+                        .build(),
+                ),
+            )
+            .await
+            .convert(Some("Error fetching submissions."))?;
+
+        cursor
+            .try_collect::<Vec<_>>()
+            .await
+            .convert(Some("Error fetching submissions."))
+    }
+
+    /// Problems any of the users has passed.
+    pub async fn get_solved_problem_ids(
+        &self,
+        user_ids: &[ObjectId],
+    ) -> Result<Vec<ObjectId>, RouteErr> {
+        let ids = self
+            .0
+            .collection::<Submission>("submissions")
+            .distinct(
+                "problemId",
+                doc! {
+                    "userId": { "$in": user_ids },
+                    "passed": true,
+                },
+                None,
+            )
+            .await
+            .convert(Some("Error fetching submissions."))?;
+
+        Ok(ids.iter().filter_map(Bson::as_object_id).collect())
+    }
+
+    pub async fn get_by_id(
+        &self,
+        id: &ObjectId,
+        user_id: &ObjectId,
+    ) -> Result<Submission, RouteErr> {
+        self.0
+            .collection::<Submission>("submissions")
+            .find_one(
+                doc! {
+                    "_id": id,
+                    "userId": user_id,
+                },
+                None,
+            )
+            .await
+            .convert(Some("Error fetching submission."))?
+            .convert(Some("Submission not found."))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SubmissionRepo
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let db = parts.extensions.get::<Db>().unwrap();
+        Ok(Self(db.clone()))
+    }
+}
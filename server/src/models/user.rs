@@ -25,6 +25,9 @@ pub struct User {
     pub access_token: String,
     pub accounts: Vec<Account>,
     pub sessions: Vec<Session>,
+    /// Set once the user has played a rated match, see `models::rating`.
+    #[serde(default)]
+    pub rating: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -94,6 +97,7 @@ impl UserRepo {
                 accounts: vec![],
                 sessions: vec![],
                 access_token: data.access_token,
+                rating: None,
             })
     }
 
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::rooms::languages::{self, Language};
+
+use super::{
+    batch, parse_marked_outputs,
+    queue::{run_job, InFlight},
+    trim_traceback, IoMode, Job, Limits, Progress,
+};
+
+/// How a program's output is compared with the expected output. Outputs of
+/// problems in stdin mode are strings, which are compared token by token
+/// except by `Exact`, `Whitespace` and `CaseInsensitive`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "t", content = "c")]
+pub enum Checker {
+    /// The outputs are the same JSON value.
+    #[default]
+    Exact,
+    /// Strings are the same apart from trailing whitespace on each line and
+    /// trailing blank lines.
+    Whitespace,
+    /// Strings have the same whitespace-separated tokens.
+    Tokens,
+    /// Numbers may differ by up to `epsilon`, absolute or relative.
+    FloatTolerance { epsilon: f64 },
+    /// Arrays may be in any order.
+    Unordered,
+    /// Strings may differ in case, and in whitespace the way `Whitespace`
+    /// allows.
+    CaseInsensitive,
+    /// A program written by the problem author decides. It defines
+    /// `check(input, expected, output)`, returning whether the output is
+    /// accepted.
+    Custom {
+        #[serde(with = "languages::by_name")]
+        language: &'static Language,
+        code: String,
+    },
+}
+
+/// One line of output from a checker template.
+#[derive(Deserialize, Debug)]
+struct CheckOutput {
+    pub index: usize,
+    pub accepted: bool,
+}
+
+impl Checker {
+    /// For problems that don't pick one. Stdout usually ends with a newline
+    /// the expected output leaves out, so it isn't compared exactly.
+    pub fn default_for(io_mode: IoMode) -> Self {
+        match io_mode {
+            IoMode::Function => Checker::Exact,
+            IoMode::Stdin => Checker::Whitespace,
+        }
+    }
+
+    /// Whether each `(input, expected, output)` is accepted.
+    pub async fn check(
+        &self,
+        cases: &[(Value, Value, Value)],
+        in_flight: &InFlight,
+        progress: &(dyn Fn(Progress) + Send + Sync),
+    ) -> anyhow::Result<Vec<bool>> {
+        let (language, code) = match self {
+            Checker::Custom { language, code } => (*language, code),
+            _ => {
+                return Ok(cases
+                    .iter()
+                    .map(|(_, expected, output)| self.accepts(expected, output))
+                    .collect())
+            }
+        };
+
+        if cases.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let limits = Limits::default();
+        let cases = cases
+            .iter()
+            .map(|(input, expected, output)| {
+                Value::Array(vec![input.clone(), expected.clone(), output.clone()])
+            })
+            .collect::<Vec<_>>();
+
+        let mut accepted = vec![false; cases.len()];
+        let mut start = 0;
+        while start < cases.len() {
+            let (count, time_limit) = batch(limits.time_limit_ms, cases.len() - start);
+            let end = start + count;
+            let output = run_job(
+                in_flight,
+                Job {
+                    language,
+                    source: language.checker_source(code, &cases[start..end])?,
+                    stdin: String::new(),
+                    time_limit,
+                    memory_limit_kb: limits.memory_limit_kb,
+                },
+                progress,
+            )
+            .await?;
+
+            let mut checked = 0;
+            for check_output in parse_marked_outputs::<CheckOutput>(&output.stdout) {
+                if let Some(slot) = accepted[start..end].get_mut(check_output.index) {
+                    *slot = check_output.accepted;
+                    checked += 1;
+                }
+            }
+
+            if checked < count {
+                return Err(anyhow::anyhow!(
+                    "Error running the problem's checker:\n{}",
+                    trim_traceback(&output.stderr)
+                ));
+            }
+            start = end;
+        }
+
+        Ok(accepted)
+    }
+
+    fn accepts(&self, expected: &Value, output: &Value) -> bool {
+        match (self, expected, output) {
+            (Checker::Whitespace, Value::String(a), Value::String(b)) => {
+                normalize_whitespace(a) == normalize_whitespace(b)
+            }
+            (Checker::Tokens, Value::String(a), Value::String(b)) => {
+                a.split_whitespace().eq(b.split_whitespace())
+            }
+            (
+                Checker::FloatTolerance { .. } | Checker::Unordered,
+                Value::String(a),
+                Value::String(b),
+            ) => {
+                let a = tokens(a);
+                let b = tokens(b);
+                self.accepts(&a, &b)
+            }
+            (Checker::FloatTolerance { epsilon }, Value::Number(a), Value::Number(b)) => {
+                match (a.as_f64(), b.as_f64()) {
+                    (Some(a), Some(b)) => within(*epsilon, a, b),
+                    _ => a == b,
+                }
+            }
+            (Checker::Unordered, Value::Array(a), Value::Array(b)) => {
+                let mut a = a.iter().map(Value::to_string).collect::<Vec<_>>();
+                let mut b = b.iter().map(Value::to_string).collect::<Vec<_>>();
+                a.sort();
+                b.sort();
+                a == b
+            }
+            (Checker::CaseInsensitive, Value::String(a), Value::String(b)) => {
+                normalize_whitespace(&a.to_lowercase()) == normalize_whitespace(&b.to_lowercase())
+            }
+            (Checker::FloatTolerance { .. } | Checker::CaseInsensitive, _, _) => {
+                match (expected, output) {
+                    (Value::Array(a), Value::Array(b)) => {
+                        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.accepts(a, b))
+                    }
+                    (Value::Object(a), Value::Object(b)) => {
+                        a.len() == b.len()
+                            && a.iter()
+                                .all(|(key, a)| b.get(key).is_some_and(|b| self.accepts(a, b)))
+                    }
+                    _ => expected == output,
+                }
+            }
+            _ => expected == output,
+        }
+    }
+}
+
+fn within(epsilon: f64, a: f64, b: f64) -> bool {
+    let difference = (a - b).abs();
+    difference <= epsilon || difference <= epsilon * a.abs().max(b.abs())
+}
+
+fn normalize_whitespace(output: &str) -> String {
+    output
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+/// Splits stdin mode output into tokens, with numbers as JSON numbers so they
+/// can be compared with a tolerance.
+fn tokens(output: &str) -> Value {
+    Value::Array(
+        output
+            .split_whitespace()
+            .map(|token| match token.parse::<f64>() {
+                Ok(number) if number.is_finite() => serde_json::json!(number),
+                _ => Value::String(token.to_string()),
+            })
+            .collect(),
+    )
+}
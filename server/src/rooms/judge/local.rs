@@ -0,0 +1,193 @@
+use std::{
+    os::unix::process::ExitStatusExt,
+    path::PathBuf,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use axum::async_trait;
+use mongodb::bson::oid::ObjectId;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    process::Command,
+    task::JoinHandle,
+};
+
+use super::{Job, JobOutput, JudgeBackend};
+
+/// How much of each of a program's stdout and stderr is kept. The rest is
+/// read and thrown away.
+const MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+/// How long to wait for the rest of a program's output once it has exited
+/// and everything it started has been killed.
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Runs jobs as child processes of the server, using the interpreters
+/// installed on the host. There is no isolation beyond rlimits and a
+/// throwaway working directory, so only use this where submissions are
+/// trusted: development, CI and small deployments.
+pub struct LocalBackend;
+
+#[async_trait]
+impl JudgeBackend for LocalBackend {
+    async fn execute(&self, job: &Job) -> anyhow::Result<JobOutput> {
+        let dir = WorkDir::create().await?;
+        tokio::fs::write(dir.0.join(job.language.file_name), &job.source).await?;
+
+        let (program, args) = job
+            .language
+            .local_command
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("{} cannot be run locally.", job.language.name))?;
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .current_dir(&dir.0)
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let cpu_seconds = job.time_limit.as_secs() + 1;
+        let memory_bytes = job.memory_limit_kb as u64 * 1024;
+        // SAFETY: only async-signal-safe calls are made between fork and exec.
+        unsafe {
+            command.pre_exec(move || {
+                // in its own process group, so anything it starts can be killed
+                // with it
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                set_rlimit(libc::RLIMIT_CPU, cpu_seconds)?;
+                set_rlimit(libc::RLIMIT_DATA, memory_bytes)?;
+                set_rlimit(libc::RLIMIT_FSIZE, 16 * 1024 * 1024)?;
+                set_rlimit(libc::RLIMIT_CORE, 0)?;
+                Ok(())
+            });
+        }
+
+        let started = Instant::now();
+        let mut child = command.spawn()?;
+        let group = child.id().map(|pid| ProcessGroup(pid as libc::pid_t));
+        let stdin = tokio::spawn(write_all(child.stdin.take(), job.stdin.clone()));
+        let stdout = tokio::spawn(read_output(child.stdout.take()));
+        let stderr = tokio::spawn(read_output(child.stderr.take()));
+
+        let (timed_out, success) = match tokio::time::timeout(job.time_limit, child.wait()).await {
+            Ok(status) => {
+                let status = status?;
+                (status.signal() == Some(libc::SIGXCPU), status.success())
+            }
+            Err(_) => {
+                child.kill().await?;
+                (true, false)
+            }
+        };
+        let runtime = started.elapsed();
+        // anything it started could still hold the pipes open
+        drop(group);
+        // the program is allowed to exit without reading all of its input
+        stdin.abort();
+
+        let dir_path = format!("{}/", dir.0.display());
+        Ok(JobOutput {
+            stdout: String::from_utf8_lossy(&finish_reading(stdout).await?).replace(&dir_path, ""),
+            stderr: String::from_utf8_lossy(&finish_reading(stderr).await?).replace(&dir_path, ""),
+            timed_out,
+            success,
+            runtime,
+        })
+    }
+}
+
+async fn write_all(pipe: Option<impl AsyncWrite + Unpin>, data: String) -> std::io::Result<()> {
+    if let Some(mut pipe) = pipe {
+        pipe.write_all(data.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Reads until the pipe is closed, keeping the first `MAX_OUTPUT_BYTES`.
+async fn read_output(pipe: Option<impl AsyncRead + Unpin>) -> std::io::Result<Vec<u8>> {
+    let mut data = vec![];
+    if let Some(mut pipe) = pipe {
+        let mut buffer = [0; 8192];
+        loop {
+            let read = pipe.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            let kept = read.min(MAX_OUTPUT_BYTES - data.len());
+            data.extend_from_slice(&buffer[..kept]);
+        }
+    }
+    Ok(data)
+}
+
+async fn finish_reading(
+    mut reading: JoinHandle<std::io::Result<Vec<u8>>>,
+) -> anyhow::Result<Vec<u8>> {
+    match tokio::time::timeout(OUTPUT_TIMEOUT, &mut reading).await {
+        Ok(data) => Ok(data??),
+        Err(_) => {
+            reading.abort();
+            Err(anyhow::anyhow!("Timed out reading the program's output."))
+        }
+    }
+}
+
+/// Kills every process in the group when dropped, including when judging is
+/// cancelled.
+struct ProcessGroup(libc::pid_t);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        unsafe {
+            libc::killpg(self.0, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(target_env = "gnu")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type Resource = libc::c_int;
+
+fn set_rlimit(resource: Resource, limit: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: limit,
+        rlim_max: limit,
+    };
+
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// A temporary directory that is removed when dropped.
+struct WorkDir(PathBuf);
+
+impl WorkDir {
+    async fn create() -> anyhow::Result<Self> {
+        let path = std::env::temp_dir().join(format!("radix-judge-{}", ObjectId::new()));
+        tokio::fs::create_dir(&path).await?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.0) {
+            log::error!(
+                "Error removing judge directory {}: {}",
+                self.0.display(),
+                err
+            );
+        }
+    }
+}
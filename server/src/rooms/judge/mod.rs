@@ -0,0 +1,615 @@
+pub mod checker;
+mod local;
+mod piston;
+pub mod queue;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::async_trait;
+use lazy_static::lazy_static;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::problem::TestCase;
+
+use self::{
+    checker::Checker,
+    local::LocalBackend,
+    piston::PistonBackend,
+    queue::{run_job, InFlight, Submitter},
+};
+use super::languages::Language;
+
+/// A single program to run: the user's code, with a runner appended unless
+/// it reads stdin.
+#[derive(Debug)]
+pub struct Job {
+    pub language: &'static Language,
+    pub source: String,
+    pub stdin: String,
+    /// For the whole program, not each test case.
+    pub time_limit: Duration,
+    pub memory_limit_kb: u32,
+}
+
+#[derive(Debug)]
+pub struct JobOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether the program was killed for running too long. Anything printed
+    /// before that is still in `stdout`.
+    pub timed_out: bool,
+    /// Whether the program exited with status 0.
+    pub success: bool,
+    pub runtime: Duration,
+}
+
+/// Somewhere jobs can be run. Selected with the `JUDGE_BACKEND` environment
+/// variable, `piston` (the default) or `local`.
+#[async_trait]
+pub trait JudgeBackend: Send + Sync {
+    async fn execute(&self, job: &Job) -> anyhow::Result<JobOutput>;
+}
+
+lazy_static! {
+    /// The longest a job can run for, if the backend has a limit.
+    static ref MAX_JOB_TIME_LIMIT_MS: Option<u32> = match dotenvy::var("JUDGE_BACKEND").as_deref() {
+        Ok("piston") | Err(_) => Some(*piston::MAX_RUN_TIMEOUT_MS),
+        _ => None,
+    };
+}
+
+/// The longest time limit a test case can have, if the backend limits how
+/// long programs run.
+pub fn max_time_limit_ms() -> Option<u32> {
+    MAX_JOB_TIME_LIMIT_MS.map(|max| max.saturating_sub(STARTUP_TIME_MS))
+}
+
+/// How many of `remaining` test cases taking up to `time_limit_ms` each can
+/// run in one program, and that program's time limit.
+fn batch(time_limit_ms: u32, remaining: usize) -> (usize, Duration) {
+    let count = match max_time_limit_ms() {
+        Some(max) => ((max / time_limit_ms.max(1)) as usize).clamp(1, remaining),
+        None => remaining,
+    };
+    let time_limit_ms = time_limit_ms as u64 * count as u64 + STARTUP_TIME_MS as u64;
+    (count, Duration::from_millis(time_limit_ms))
+}
+
+fn create_backend() -> anyhow::Result<Arc<dyn JudgeBackend>> {
+    match dotenvy::var("JUDGE_BACKEND").as_deref() {
+        Ok("piston") | Err(_) => Ok(Arc::new(PistonBackend::new())),
+        Ok("local") => Ok(Arc::new(LocalBackend)),
+        Ok(other) => Err(anyhow::anyhow!("Unknown judge backend: {}", other)),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "t", content = "c")]
+pub enum Verdict {
+    Accepted,
+    WrongAnswer {
+        output: String,
+    },
+    RuntimeError {
+        traceback: String,
+    },
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    /// Skipped because an earlier test case exceeded a limit.
+    NotRun,
+}
+
+impl Verdict {
+    fn exceeds_limit(&self) -> bool {
+        matches!(
+            self,
+            Verdict::TimeLimitExceeded | Verdict::MemoryLimitExceeded
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCaseResult {
+    pub input: String,
+    pub expected: String,
+    pub verdict: Verdict,
+    pub runtime: u32,
+    /// The test case is hidden, so its input, expected output and the
+    /// program's output and traceback are left out.
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct JudgingResults {
+    pub results: Vec<TestCaseResult>,
+    pub runtime: u32,
+}
+
+impl JudgingResults {
+    pub fn all_passed(&self) -> bool {
+        self.results
+            .iter()
+            .all(|result| matches!(result.verdict, Verdict::Accepted))
+    }
+
+    /// What a submitter gets to see of the judged `test_cases`: everything up
+    /// to the first failure but no further, without the details of hidden
+    /// test cases.
+    pub fn visible(&self, test_cases: &[TestCase]) -> Vec<TestCaseResult> {
+        let shown = match self
+            .results
+            .iter()
+            .position(|result| !matches!(result.verdict, Verdict::Accepted))
+        {
+            Some(first_failure) => &self.results[..=first_failure],
+            None => &self.results,
+        };
+
+        shown
+            .iter()
+            .zip(test_cases)
+            .map(|(result, test_case)| {
+                if test_case.sample {
+                    return result.clone();
+                }
+
+                TestCaseResult {
+                    input: String::new(),
+                    expected: String::new(),
+                    verdict: match &result.verdict {
+                        Verdict::WrongAnswer { .. } => Verdict::WrongAnswer {
+                            output: String::new(),
+                        },
+                        // the program could print the input in its traceback
+                        Verdict::RuntimeError { .. } => Verdict::RuntimeError {
+                            traceback: String::new(),
+                        },
+                        verdict => verdict.clone(),
+                    },
+                    runtime: result.runtime,
+                    hidden: true,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Resource limits for each test case.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Limits {
+    pub time_limit_ms: u32,
+    pub memory_limit_kb: u32,
+}
+
+pub const DEFAULT_TIME_LIMIT_MS: u32 = 2000;
+pub const DEFAULT_MEMORY_LIMIT_KB: u32 = 256 * 1024;
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            time_limit_ms: DEFAULT_TIME_LIMIT_MS,
+            memory_limit_kb: DEFAULT_MEMORY_LIMIT_KB,
+        }
+    }
+}
+
+/// How a program is given a test case's input and gives back its output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IoMode {
+    /// `solve` is called with the input as JSON arguments and returns the
+    /// output.
+    #[default]
+    Function,
+    /// The input is fed to the program as stdin and its stdout is the output.
+    Stdin,
+}
+
+/// Everything about a problem that decides how its test cases are judged.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub limits: Limits,
+    pub checker: Checker,
+    pub io_mode: IoMode,
+}
+
+/// How far along a judging request is, reported as it happens.
+#[derive(Debug, Clone, Copy)]
+pub enum Progress {
+    /// Waiting for a worker, behind `position - 1` other jobs.
+    Queued {
+        position: usize,
+    },
+    Running,
+    /// `done` of the `total` test cases have run.
+    TestDone {
+        done: usize,
+        total: usize,
+    },
+    /// Sent last, whether judging succeeded or not.
+    Finished,
+}
+
+/// One line of output from a runner template, printed after each test case.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TestOutput {
+    pub index: usize,
+    pub runtime: u32,
+    #[serde(default)]
+    pub output: Value,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub memory_exceeded: bool,
+}
+
+const TEST_OUTPUT_MARKER: &str = "[[RADIX TEST OUTPUT]] ";
+/// Printed by runner templates once the user's code has loaded, right before
+/// the first test case.
+const TESTS_STARTED_MARKER: &str = "[[RADIX TESTS STARTED]]";
+const MAX_TRACEBACK_LINES: usize = 20;
+/// Extra time given to each program for starting up the interpreter.
+const STARTUP_TIME_MS: u32 = 1000;
+
+/// Runs `code` against every test case and gives each its own verdict.
+pub async fn judge(
+    language: &'static Language,
+    code: &str,
+    test_cases: &[TestCase],
+    settings: &Settings,
+    submitter: &Submitter,
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) -> anyhow::Result<JudgingResults> {
+    let results = judge_inner(language, code, test_cases, settings, submitter, progress).await;
+    progress(Progress::Finished);
+    results
+}
+
+async fn judge_inner(
+    language: &'static Language,
+    code: &str,
+    test_cases: &[TestCase],
+    settings: &Settings,
+    submitter: &Submitter,
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) -> anyhow::Result<JudgingResults> {
+    let in_flight = queue::begin(submitter)?;
+
+    let limits = settings.limits;
+    let (mut results, outputs) = match settings.io_mode {
+        IoMode::Function => {
+            run_function(language, code, test_cases, limits, &in_flight, progress).await?
+        }
+        IoMode::Stdin => {
+            run_stdin(language, code, test_cases, limits, &in_flight, progress).await?
+        }
+    };
+
+    let accepted = settings
+        .checker
+        .check(
+            &outputs
+                .iter()
+                .map(|output| output.case.clone())
+                .collect::<Vec<_>>(),
+            &in_flight,
+            progress,
+        )
+        .await?;
+
+    for (output, accepted) in outputs.into_iter().zip(accepted) {
+        if !accepted {
+            let (.., got) = output.case;
+            results[output.index].verdict = Verdict::WrongAnswer {
+                output: match got {
+                    Value::String(got) if settings.io_mode == IoMode::Stdin => got,
+                    got => got.to_string(),
+                },
+            };
+        }
+    }
+
+    Ok(JudgingResults {
+        runtime: results.iter().map(|result| result.runtime).sum(),
+        results,
+    })
+}
+
+/// A test case that ran to completion and still needs its output checked.
+struct Unchecked {
+    /// Into the results.
+    index: usize,
+    /// `(input, expected, output)`
+    case: (Value, Value, Value),
+}
+
+/// Calls `solve` with each test case's input.
+///
+/// As many remaining test cases as the backend allows time for are run in one
+/// program. If it dies partway through, the test case it died on gets a
+/// verdict from how it died and the rest are run in a new program. Once a test
+/// case exceeds a limit, the rest would most likely exceed it too, so they
+/// aren't run.
+async fn run_function(
+    language: &'static Language,
+    code: &str,
+    test_cases: &[TestCase],
+    limits: Limits,
+    in_flight: &InFlight,
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) -> anyhow::Result<(Vec<TestCaseResult>, Vec<Unchecked>)> {
+    let inputs = parse_inputs(test_cases)?;
+    let mut results = Vec::with_capacity(test_cases.len());
+    let mut outputs = vec![];
+
+    while results.len() < test_cases.len() {
+        let start = results.len();
+        let (count, time_limit) = batch(limits.time_limit_ms, test_cases.len() - start);
+        let end = start + count;
+        let output = run_job(
+            in_flight,
+            Job {
+                language,
+                source: language.source(code, &inputs[start..end])?,
+                stdin: String::new(),
+                time_limit,
+                memory_limit_kb: limits.memory_limit_kb,
+            },
+            progress,
+        )
+        .await?;
+
+        for test_output in parse_marked_outputs::<TestOutput>(&output.stdout) {
+            let index = start + test_output.index;
+            let test_case = match test_cases.get(index) {
+                Some(test_case) if index == results.len() && index < end => test_case,
+                _ => return Err(anyhow::anyhow!("Program output was malformed.")),
+            };
+
+            let runtime = test_output.runtime;
+            let verdict = match failure(test_output, limits) {
+                Ok(output) => {
+                    outputs.push(Unchecked {
+                        index,
+                        case: (
+                            inputs[index].clone(),
+                            serde_json::from_str::<Value>(&test_case.output)?,
+                            output,
+                        ),
+                    });
+                    // until the checker says otherwise
+                    Verdict::Accepted
+                }
+                Err(verdict) => verdict,
+            };
+
+            let exceeded_limit = verdict.exceeds_limit();
+            results.push(TestCaseResult {
+                input: test_case.input.clone(),
+                expected: test_case.output.clone(),
+                runtime,
+                verdict,
+                hidden: false,
+            });
+            progress(Progress::TestDone {
+                done: results.len(),
+                total: test_cases.len(),
+            });
+
+            if exceeded_limit {
+                skip_rest(&mut results, test_cases, progress);
+                return Ok((results, outputs));
+            }
+        }
+
+        if results.len() == end {
+            continue;
+        }
+
+        // the program died on this test case
+        let test_case = &test_cases[results.len()];
+        let verdict = if output.timed_out {
+            Verdict::TimeLimitExceeded
+        } else if is_out_of_memory(&output.stderr) {
+            Verdict::MemoryLimitExceeded
+        } else if results.is_empty() && !output.stdout.contains(TESTS_STARTED_MARKER) {
+            // died before any test case started, so the code itself is broken.
+            // Once they have started, stderr could hold hidden inputs, so it is
+            // only shown as a test case's traceback, which `visible` redacts
+            return Err(anyhow::anyhow!(
+                "Error running code:\n{}",
+                trim_traceback(&output.stderr)
+            ));
+        } else {
+            Verdict::RuntimeError {
+                traceback: trim_traceback(&output.stderr),
+            }
+        };
+
+        // limits apply to the whole program, so give a test case that was not
+        // the first to run a fresh program before blaming it
+        let exceeded_limit = verdict.exceeds_limit();
+        if exceeded_limit && results.len() > start {
+            continue;
+        }
+
+        results.push(TestCaseResult {
+            input: test_case.input.clone(),
+            expected: test_case.output.clone(),
+            runtime: match verdict {
+                Verdict::TimeLimitExceeded => limits.time_limit_ms,
+                _ => 0,
+            },
+            verdict,
+            hidden: false,
+        });
+        progress(Progress::TestDone {
+            done: results.len(),
+            total: test_cases.len(),
+        });
+
+        if exceeded_limit {
+            skip_rest(&mut results, test_cases, progress);
+        }
+    }
+
+    Ok((results, outputs))
+}
+
+/// Gives the test cases without results yet `NotRun`, after one exceeded a
+/// limit.
+fn skip_rest(
+    results: &mut Vec<TestCaseResult>,
+    test_cases: &[TestCase],
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) {
+    results.extend(
+        test_cases[results.len()..]
+            .iter()
+            .map(|test_case| TestCaseResult {
+                input: test_case.input.clone(),
+                expected: test_case.output.clone(),
+                runtime: 0,
+                verdict: Verdict::NotRun,
+                hidden: false,
+            }),
+    );
+    progress(Progress::TestDone {
+        done: results.len(),
+        total: test_cases.len(),
+    });
+}
+
+/// Runs the program once per test case, with the input as stdin, until one
+/// exceeds a limit.
+async fn run_stdin(
+    language: &'static Language,
+    code: &str,
+    test_cases: &[TestCase],
+    limits: Limits,
+    in_flight: &InFlight,
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) -> anyhow::Result<(Vec<TestCaseResult>, Vec<Unchecked>)> {
+    let mut results = Vec::with_capacity(test_cases.len());
+    let mut outputs = vec![];
+
+    for (index, test_case) in test_cases.iter().enumerate() {
+        let output = run_job(
+            in_flight,
+            Job {
+                language,
+                source: code.to_string(),
+                stdin: test_case.input.clone(),
+                time_limit: batch(limits.time_limit_ms, 1).1,
+                memory_limit_kb: limits.memory_limit_kb,
+            },
+            progress,
+        )
+        .await?;
+
+        // the measured runtime includes starting the interpreter, and for
+        // Piston the request itself, which the job's time limit allows for, so
+        // only being killed counts
+        let verdict = if output.timed_out {
+            Verdict::TimeLimitExceeded
+        } else if is_out_of_memory(&output.stderr) {
+            Verdict::MemoryLimitExceeded
+        } else if !output.success {
+            Verdict::RuntimeError {
+                traceback: trim_traceback(&output.stderr),
+            }
+        } else {
+            outputs.push(Unchecked {
+                index,
+                case: (
+                    Value::String(test_case.input.clone()),
+                    Value::String(test_case.output.clone()),
+                    Value::String(output.stdout),
+                ),
+            });
+            // until the checker says otherwise
+            Verdict::Accepted
+        };
+
+        let exceeded_limit = verdict.exceeds_limit();
+        results.push(TestCaseResult {
+            input: test_case.input.clone(),
+            expected: test_case.output.clone(),
+            runtime: match verdict {
+                Verdict::TimeLimitExceeded => limits.time_limit_ms,
+                _ => (output.runtime.as_millis() as u32).min(limits.time_limit_ms),
+            },
+            verdict,
+            hidden: false,
+        });
+        progress(Progress::TestDone {
+            done: results.len(),
+            total: test_cases.len(),
+        });
+
+        if exceeded_limit {
+            skip_rest(&mut results, test_cases, progress);
+            break;
+        }
+    }
+
+    Ok((results, outputs))
+}
+
+fn parse_inputs(test_cases: &[TestCase]) -> anyhow::Result<Vec<Value>> {
+    test_cases
+        .iter()
+        .map(|test_case| {
+            serde_json::from_str::<Value>(&test_case.input)
+                .map_err(|_| anyhow::anyhow!("Invalid test case input: {}", test_case.input))
+        })
+        .collect()
+}
+
+/// Every line of `stdout` printed by a template, skipping anything the
+/// program printed itself.
+fn parse_marked_outputs<T: DeserializeOwned>(stdout: &str) -> Vec<T> {
+    stdout
+        .lines()
+        .filter_map(|line| line.split_once(TEST_OUTPUT_MARKER))
+        .filter_map(|(_, json)| serde_json::from_str::<T>(json.trim()).ok())
+        .collect()
+}
+
+/// The output of a test case that ran to completion within its limits, or its
+/// verdict otherwise.
+fn failure(test_output: TestOutput, limits: Limits) -> Result<Value, Verdict> {
+    if test_output.memory_exceeded {
+        return Err(Verdict::MemoryLimitExceeded);
+    }
+
+    if test_output.runtime > limits.time_limit_ms {
+        return Err(Verdict::TimeLimitExceeded);
+    }
+
+    if let Some(traceback) = test_output.error {
+        return Err(Verdict::RuntimeError {
+            traceback: trim_traceback(&traceback),
+        });
+    }
+
+    Ok(test_output.output)
+}
+
+fn is_out_of_memory(stderr: &str) -> bool {
+    stderr.contains("MemoryError") || stderr.contains("out of memory")
+}
+
+/// Keeps the end of a traceback, which is where the error is.
+fn trim_traceback(traceback: &str) -> String {
+    if traceback.trim().is_empty() {
+        return "Program exited unexpectedly.".into();
+    }
+
+    let lines = traceback.trim_end().lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(MAX_TRACEBACK_LINES)..].join("\n")
+}
@@ -0,0 +1,75 @@
+use std::time::Instant;
+
+use axum::async_trait;
+use lazy_static::lazy_static;
+use piston_rs::{Client, Executor, File};
+use regex::Regex;
+
+use super::{Job, JobOutput, JudgeBackend};
+
+lazy_static! {
+    static ref PISTON_SLASH_JOB: Regex = Regex::new("/piston/jobs/[a-zA-Z0-9-]+/").unwrap();
+    /// Piston refuses jobs with a longer run timeout than it is configured to
+    /// allow, 3 seconds by default.
+    pub static ref MAX_RUN_TIMEOUT_MS: u32 = dotenvy::var("PISTON_MAX_RUN_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3000);
+}
+
+/// Runs jobs on a Piston instance, at `PISTON_URL` if set or the public API
+/// otherwise.
+pub struct PistonBackend {
+    client: Client,
+}
+
+impl PistonBackend {
+    pub fn new() -> Self {
+        let client = if let Ok(url) = dotenvy::var("PISTON_URL") {
+            Client::with_url(&url)
+        } else {
+            Client::default()
+        };
+
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl JudgeBackend for PistonBackend {
+    async fn execute(&self, job: &Job) -> anyhow::Result<JobOutput> {
+        let executor = Executor::new()
+            .set_language(job.language.piston_language)
+            .set_version(job.language.piston_version)
+            .add_files(vec![File::new(job.language.file_name, &job.source, "utf8")])
+            .set_stdin(&job.stdin)
+            .set_run_timeout(job.time_limit.as_millis().min(*MAX_RUN_TIMEOUT_MS as u128) as isize)
+            .set_run_memory_limit(job.memory_limit_kb as isize * 1024);
+
+        let started = Instant::now();
+        let result = self
+            .client
+            .execute(&executor)
+            .await
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+        if result.is_err() {
+            // refused jobs come back with the response as their stderr
+            return Err(anyhow::anyhow!("Judge error: {}", result.run.stderr));
+        }
+        // includes the round trip to Piston, which is the best we can do
+        let runtime = started.elapsed();
+
+        Ok(JobOutput {
+            stdout: PISTON_SLASH_JOB
+                .replace_all(&result.run.stdout, "")
+                .to_string(),
+            stderr: PISTON_SLASH_JOB
+                .replace_all(&result.run.stderr, "")
+                .to_string(),
+            // Piston kills programs that run past their timeout
+            timed_out: result.run.signal.as_deref() == Some("SIGKILL"),
+            success: result.run.code == Some(0),
+            runtime,
+        })
+    }
+}
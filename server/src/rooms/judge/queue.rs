@@ -0,0 +1,420 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use mongodb::bson::{oid::ObjectId, Uuid};
+use parking_lot::{Mutex, RwLock};
+use tokio::{
+    select,
+    sync::{oneshot, watch, Notify, Semaphore},
+    time::{self, MissedTickBehavior},
+};
+
+use super::{create_backend, Job, JobOutput, JudgeBackend, Progress};
+
+/// How many jobs are run at once and how quickly they are sent to the
+/// backend. More workers means less waiting, but more load on the backend and
+/// the host.
+#[derive(Debug, Clone)]
+struct QueueConfig {
+    /// `JUDGE_WORKERS`, 4 by default.
+    workers: usize,
+    /// `JUDGE_REQUESTS_PER_SECOND`, unlimited by default. The public Piston
+    /// API allows 5. Kept within `REQUESTS_PER_SECOND_RANGE`.
+    requests_per_second: Option<f64>,
+    /// `JUDGE_QUEUE_CAPACITY`, 500 by default. Once this many jobs are
+    /// waiting, new jobs are refused.
+    capacity: usize,
+    /// `JUDGE_MAX_IN_FLIGHT_PER_USER`, 2 by default. How many submissions
+    /// and test runs one user can have being judged at once.
+    max_in_flight_per_user: usize,
+}
+
+/// From one request an hour to one a millisecond, so the time between
+/// requests is never zero or too long to represent.
+const REQUESTS_PER_SECOND_RANGE: RangeInclusive<f64> = 1.0 / 3600.0..=1000.0;
+
+impl QueueConfig {
+    fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            dotenvy::var(name).ok().and_then(|v| v.parse().ok())
+        }
+
+        Self {
+            workers: var("JUDGE_WORKERS").unwrap_or(4).max(1),
+            requests_per_second: var::<f64>("JUDGE_REQUESTS_PER_SECOND")
+                .filter(|rps| *rps > 0.0)
+                .map(|rps| {
+                    rps.clamp(
+                        *REQUESTS_PER_SECOND_RANGE.start(),
+                        *REQUESTS_PER_SECOND_RANGE.end(),
+                    )
+                }),
+            capacity: var("JUDGE_QUEUE_CAPACITY").unwrap_or(500).max(1),
+            max_in_flight_per_user: var("JUDGE_MAX_IN_FLIGHT_PER_USER").unwrap_or(2).max(1),
+        }
+    }
+}
+
+/// Submissions are run before test runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Submission,
+    Test,
+}
+
+/// Who a judging request is for, used to share the workers fairly.
+#[derive(Debug, Clone, Copy)]
+pub struct Submitter {
+    pub user: ObjectId,
+    /// `None` when practicing outside of a room. Everyone practicing shares a
+    /// turn, like a room.
+    pub room: Option<Uuid>,
+    pub priority: Priority,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JobState {
+    /// With this many jobs ahead of it.
+    Queued(usize),
+    Running,
+}
+
+struct QueuedJob {
+    id: u64,
+    job: Job,
+    tx: oneshot::Sender<anyhow::Result<JobOutput>>,
+    state: watch::Sender<JobState>,
+    /// Kept until the backend is done with the job, even if whoever queued
+    /// it stops waiting.
+    _slot: Arc<Slot>,
+}
+
+/// Each user's waiting jobs in one room.
+type RoomJobs = VecDeque<(ObjectId, VecDeque<QueuedJob>)>;
+
+/// Waiting jobs of one priority. Rooms take turns, and so do the users in
+/// each room, so nobody can starve anyone else by queueing lots of jobs.
+#[derive(Default)]
+struct Level {
+    rooms: VecDeque<(Option<Uuid>, RoomJobs)>,
+}
+
+impl Level {
+    fn push(&mut self, submitter: &Submitter, job: QueuedJob) {
+        let users = match self.rooms.iter_mut().find(|(id, _)| *id == submitter.room) {
+            Some((_, users)) => users,
+            None => {
+                self.rooms.push_back((submitter.room, VecDeque::new()));
+                &mut self.rooms.back_mut().unwrap().1
+            }
+        };
+
+        match users.iter_mut().find(|(id, _)| *id == submitter.user) {
+            Some((_, jobs)) => jobs.push_back(job),
+            None => users.push_back((submitter.user, VecDeque::from([job]))),
+        }
+    }
+
+    /// The jobs in the order `pop` would return them.
+    fn in_order(&self) -> Vec<&QueuedJob> {
+        let mut order = vec![];
+        let mut rooms = self
+            .rooms
+            .iter()
+            .map(|(_, users)| users.iter().map(|(_, jobs)| jobs.iter()).collect())
+            .collect::<VecDeque<VecDeque<_>>>();
+
+        while let Some(mut users) = rooms.pop_front() {
+            if let Some(mut jobs) = users.pop_front() {
+                order.extend(jobs.next());
+                if jobs.len() > 0 {
+                    users.push_back(jobs);
+                }
+            }
+            if !users.is_empty() {
+                rooms.push_back(users);
+            }
+        }
+
+        order
+    }
+
+    fn remove(&mut self, id: u64) -> bool {
+        let mut removed = false;
+        for (_, users) in &mut self.rooms {
+            for (_, jobs) in users.iter_mut() {
+                if let Some(index) = jobs.iter().position(|job| job.id == id) {
+                    jobs.remove(index);
+                    removed = true;
+                }
+            }
+            users.retain(|(_, jobs)| !jobs.is_empty());
+        }
+        self.rooms.retain(|(_, users)| !users.is_empty());
+
+        removed
+    }
+
+    fn pop(&mut self) -> Option<QueuedJob> {
+        let (room, mut users) = self.rooms.pop_front()?;
+        let (user, mut jobs) = users.pop_front()?;
+        let job = jobs.pop_front();
+
+        if !jobs.is_empty() {
+            users.push_back((user, jobs));
+        }
+        if !users.is_empty() {
+            self.rooms.push_back((room, users));
+        }
+
+        job
+    }
+}
+
+#[derive(Default)]
+struct Scheduler {
+    submissions: Level,
+    tests: Level,
+    queued: usize,
+}
+
+impl Scheduler {
+    fn push(&mut self, submitter: &Submitter, job: QueuedJob) {
+        self.queued += 1;
+        match submitter.priority {
+            Priority::Submission => self.submissions.push(submitter, job),
+            Priority::Test => self.tests.push(submitter, job),
+        }
+        self.update_positions();
+    }
+
+    fn pop(&mut self) -> Option<QueuedJob> {
+        let job = self.submissions.pop().or_else(|| self.tests.pop())?;
+        self.queued -= 1;
+        self.update_positions();
+        Some(job)
+    }
+
+    fn remove(&mut self, id: u64) {
+        if self.submissions.remove(id) || self.tests.remove(id) {
+            self.queued -= 1;
+            self.update_positions();
+        }
+    }
+
+    fn update_positions(&self) {
+        let order = self.submissions.in_order().into_iter();
+        for (position, job) in order.chain(self.tests.in_order()).enumerate() {
+            job.state.send_if_modified(|state| {
+                let changed = *state != JobState::Queued(position);
+                *state = JobState::Queued(position);
+                changed
+            });
+        }
+    }
+}
+
+struct JobQueue {
+    config: QueueConfig,
+    scheduler: Mutex<Scheduler>,
+    available: Notify,
+    in_flight: Mutex<HashMap<ObjectId, usize>>,
+}
+
+static JOB_QUEUE: RwLock<Option<Arc<JobQueue>>> = RwLock::new(None);
+static NEXT_JOB_ID: AtomicU64 = AtomicU64::new(0);
+
+fn job_queue() -> anyhow::Result<Arc<JobQueue>> {
+    if let Some(job_queue) = JOB_QUEUE.read().as_ref() {
+        return Ok(job_queue.clone());
+    }
+
+    let mut job_queue = JOB_QUEUE.write();
+    if job_queue.is_none() {
+        *job_queue = Some(start()?);
+    }
+
+    Ok(job_queue.as_ref().unwrap().clone())
+}
+
+/// A judging request started by `begin`. Its jobs are queued with `run_job`.
+pub struct InFlight {
+    submitter: Submitter,
+    slot: Arc<Slot>,
+}
+
+/// Counts towards a user's in-flight judging requests until the request and
+/// every job it started have finished.
+struct Slot {
+    job_queue: Arc<JobQueue>,
+    user: ObjectId,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut in_flight = self.job_queue.in_flight.lock();
+        if let Some(count) = in_flight.get_mut(&self.user) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.user);
+            }
+        }
+    }
+}
+
+/// Starts a judging request, or refuses it if the submitter has too many
+/// already.
+pub fn begin(submitter: &Submitter) -> anyhow::Result<InFlight> {
+    let job_queue = job_queue()?;
+
+    {
+        let mut in_flight = job_queue.in_flight.lock();
+        let count = in_flight.entry(submitter.user).or_default();
+        if *count >= job_queue.config.max_in_flight_per_user {
+            return Err(anyhow::anyhow!(
+                "You already have {} submissions being judged. Wait for them to finish.",
+                count
+            ));
+        }
+        *count += 1;
+    }
+
+    Ok(InFlight {
+        submitter: *submitter,
+        slot: Arc::new(Slot {
+            job_queue,
+            user: submitter.user,
+        }),
+    })
+}
+
+/// Takes a job back out of the queue if it is dropped before the job has
+/// started, so cancelled jobs never run.
+struct Dequeue<'a> {
+    job_queue: &'a JobQueue,
+    id: u64,
+}
+
+impl Drop for Dequeue<'_> {
+    fn drop(&mut self) {
+        self.job_queue.scheduler.lock().remove(self.id);
+    }
+}
+
+/// Queues a job and waits for its output, reporting its place in the queue
+/// and when it starts running. Dropping the future cancels the job if it
+/// hasn't started yet, or discards its output if it has.
+pub async fn run_job(
+    in_flight: &InFlight,
+    job: Job,
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) -> anyhow::Result<JobOutput> {
+    let (tx, mut rx) = oneshot::channel::<anyhow::Result<JobOutput>>();
+    let (state, mut state_rx) = watch::channel(JobState::Queued(0));
+
+    let submitter = &in_flight.submitter;
+    let job_queue = in_flight.slot.job_queue.clone();
+    let id = NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed);
+    {
+        let mut scheduler = job_queue.scheduler.lock();
+        if scheduler.queued >= job_queue.config.capacity {
+            return Err(anyhow::anyhow!("The judge is too busy, try again later."));
+        }
+        scheduler.push(
+            submitter,
+            QueuedJob {
+                id,
+                job,
+                tx,
+                state,
+                _slot: in_flight.slot.clone(),
+            },
+        );
+    }
+    job_queue.available.notify_one();
+    let _dequeue = Dequeue {
+        job_queue: &job_queue,
+        id,
+    };
+
+    let mut reported = None;
+    loop {
+        let current = *state_rx.borrow_and_update();
+        if reported != Some(current) {
+            reported = Some(current);
+            progress(match current {
+                JobState::Queued(ahead) => Progress::Queued {
+                    position: ahead + 1,
+                },
+                JobState::Running => Progress::Running,
+            });
+        }
+
+        select! {
+            output = &mut rx => {
+                return output.map_err(|_| anyhow::anyhow!("Judge queue closed."))?;
+            }
+            Ok(()) = state_rx.changed() => (),
+        }
+    }
+}
+
+fn start() -> anyhow::Result<Arc<JobQueue>> {
+    let backend = create_backend()?;
+    let config = QueueConfig::from_env();
+    log::info!("Starting judge queue: {:?}", config);
+
+    let job_queue = Arc::new(JobQueue {
+        config,
+        scheduler: Default::default(),
+        available: Notify::new(),
+        in_flight: Default::default(),
+    });
+    tokio::spawn(dispatch(job_queue.clone(), backend));
+
+    Ok(job_queue)
+}
+
+/// Hands jobs to workers as workers free up and the rate limit allows. The
+/// next job is only picked once a worker is free, so it is always the one
+/// that should run next at that moment.
+async fn dispatch(job_queue: Arc<JobQueue>, backend: Arc<dyn JudgeBackend>) {
+    let workers = Arc::new(Semaphore::new(job_queue.config.workers));
+    let mut rate_limit = job_queue.config.requests_per_second.map(|rps| {
+        let mut interval = time::interval(Duration::from_secs_f64(1.0 / rps));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
+
+    loop {
+        let worker = workers
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("worker semaphore is never closed");
+        if let Some(rate_limit) = &mut rate_limit {
+            rate_limit.tick().await;
+        }
+
+        let queued = loop {
+            if let Some(queued) = job_queue.scheduler.lock().pop() {
+                break queued;
+            }
+            job_queue.available.notified().await;
+        };
+
+        let backend = backend.clone();
+        tokio::spawn(async move {
+            let _ = queued.state.send(JobState::Running);
+            let _ = queued.tx.send(backend.execute(&queued.job).await);
+            drop(worker);
+        });
+    }
+}
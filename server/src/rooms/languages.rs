@@ -0,0 +1,104 @@
+use std::fmt;
+
+use serde::{Deserializer, Serializer};
+use serde_json::Value;
+
+/// A language submissions can be written in, and everything the judge needs
+/// to know to run it.
+pub struct Language {
+    /// The name clients use, also the key into `Code`.
+    pub name: &'static str,
+    pub piston_language: &'static str,
+    pub piston_version: &'static str,
+    pub file_name: &'static str,
+    /// Appended after the user's code. `{{INPUTS}}` is replaced with the output
+    /// of `serialize_inputs`.
+    pub template: &'static str,
+    /// Appended after a problem's custom checker, in the same way.
+    pub checker_template: &'static str,
+    pub serialize_inputs: fn(&[Value]) -> anyhow::Result<String>,
+    /// How the local backend runs `file_name`, program first.
+    pub local_command: &'static [&'static str],
+}
+
+pub static LANGUAGES: &[Language] = &[
+    Language {
+        name: "python",
+        piston_language: "python",
+        piston_version: "3.10.0",
+        file_name: "main.py",
+        template: include_str!("./templates/python-runner.py"),
+        checker_template: include_str!("./templates/python-checker.py"),
+        serialize_inputs: as_string_literal,
+        local_command: &["python3", "main.py"],
+    },
+    Language {
+        name: "javascript",
+        piston_language: "javascript",
+        piston_version: "18.15.0",
+        file_name: "main.js",
+        template: include_str!("./templates/javascript-runner.js"),
+        checker_template: include_str!("./templates/javascript-checker.js"),
+        serialize_inputs: as_literal,
+        local_command: &["node", "main.js"],
+    },
+];
+
+impl fmt::Debug for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Language").field(&self.name).finish()
+    }
+}
+
+pub fn get(name: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|language| language.name == name)
+}
+
+impl Language {
+    pub fn source(&self, code: &str, inputs: &[Value]) -> anyhow::Result<String> {
+        self.fill(self.template, code, inputs)
+    }
+
+    pub fn checker_source(&self, code: &str, inputs: &[Value]) -> anyhow::Result<String> {
+        self.fill(self.checker_template, code, inputs)
+    }
+
+    fn fill(&self, template: &str, code: &str, inputs: &[Value]) -> anyhow::Result<String> {
+        let runner = template.replace("{{INPUTS}}", &(self.serialize_inputs)(inputs)?);
+
+        Ok(format!("{}\n\n{}", code, runner))
+    }
+}
+
+/// The inputs as a JSON document, for languages where that is already a valid
+/// expression.
+fn as_literal(inputs: &[Value]) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(inputs)?)
+}
+
+/// The inputs as a string literal containing a JSON document, to be parsed by
+/// the runner.
+fn as_string_literal(inputs: &[Value]) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(&serde_json::to_string(inputs)?)?)
+}
+
+/// (De)serializes a `&'static Language` as its name.
+pub mod by_name {
+    use super::*;
+
+    pub fn serialize<S>(language: &&'static Language, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(language.name)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<&'static Language, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = <String as serde::Deserialize>::deserialize(deserializer)?;
+        get(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unsupported language: {}", name)))
+    }
+}
@@ -1,3 +1,5 @@
 pub mod connection;
 pub mod judge;
+pub mod languages;
 pub mod room;
+pub mod standings;
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use mongodb::bson::{oid::ObjectId, Uuid};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
@@ -8,15 +9,29 @@ use tokio::{
 };
 
 use crate::{
+    errors::RouteErr,
     models::{
-        problem::{Code, Problem, PublicProblem, TestCase},
+        matches::{CreateMatch, MatchRepo},
+        problem::{Code, Problem, ProblemRepo, PublicProblem, TestCase},
+        rating::RatingRepo,
+        submission::{CreateSubmission, SubmissionRepo},
         user::{PublicUser, User},
     },
-    rooms::judge,
+    rooms::{
+        judge,
+        languages::{self, Language},
+    },
     routers::rooms::ProblemsFilter,
 };
 
-use super::{connection::ConnectionCommands, judge::FailedTestCase};
+use super::{
+    connection::ConnectionCommands,
+    judge::{
+        queue::{Priority, Submitter},
+        JudgingResults, Progress, Settings, TestCaseResult,
+    },
+    standings::{Scoreboard, Scoring, Standing},
+};
 
 #[derive(Debug)]
 pub enum RoomCommands {
@@ -26,6 +41,24 @@ pub enum RoomCommands {
     RemoveConnection(ConnId),
 
     ClientSent(ConnId, ClientSentCommand),
+    /// Sent by a judging task started with `Room::spawn_judge`, with the id it
+    /// was given.
+    JudgeFinished(u64, UserId, Judging, anyhow::Result<JudgingResults>),
+    /// Sent by the round timer whenever the countdown should be broadcast,
+    /// and when the round is over.
+    RoundTick,
+}
+
+/// What code is being judged for.
+#[derive(Debug, Clone, Copy)]
+pub enum Judging {
+    Test,
+    /// `round` is the number of the round it was submitted in.
+    Submission {
+        problem_index: u32,
+        round: u32,
+        submitted_at: DateTime<Utc>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -42,13 +75,20 @@ pub enum ClientSentCommand {
     TestCode {
         #[serde(rename = "testCases")]
         test_cases: Vec<TestCase>,
-        language: String,
+        #[serde(with = "languages::by_name")]
+        language: &'static Language,
+        /// The problem whose limits, checker and I/O mode to use, if any.
+        #[serde(rename = "problemIndex", default)]
+        problem_index: Option<u32>,
     },
     SubmitCode {
         #[serde(rename = "problemIndex")]
         problem_index: u32,
-        language: String,
+        #[serde(with = "languages::by_name")]
+        language: &'static Language,
     },
+    /// Stops judging everything the user has sent for judging.
+    CancelJudge,
 }
 
 #[derive(Serialize, Debug)]
@@ -63,26 +103,58 @@ pub enum ServerSentCommand {
         name: String,
         public: bool,
         owner: PublicUser,
+        #[serde(rename = "roundDurationSecs")]
+        round_duration_secs: u32,
+        scoring: Scoring,
     },
     SetProblems(Option<Vec<PublicProblem>>),
     SetTestResponse(TestResponse),
+    JudgeQueued {
+        position: usize,
+    },
+    JudgeRunning,
+    JudgeTestDone {
+        done: usize,
+        total: usize,
+    },
+    JudgeFinished,
+    SetStandings(Vec<Standing>),
+    SetRoundTimer {
+        #[serde(rename = "endsAt")]
+        ends_at: DateTime<Utc>,
+        #[serde(rename = "secondsLeft")]
+        seconds_left: i64,
+    },
+    RoundEnd {
+        standings: Vec<Standing>,
+    },
+}
+
+/// Streams judge progress to a connection. Updates are dropped rather than
+/// waited for if the connection is backed up, since newer ones replace them.
+/// `JudgeFinished` isn't one of them, the room sends it after the result with
+/// `send_judge_response`.
+fn report_progress(commands: Sender<ConnectionCommands>) -> impl Fn(Progress) + Send + Sync {
+    move |progress| {
+        let command = match progress {
+            Progress::Queued { position } => ServerSentCommand::JudgeQueued { position },
+            Progress::Running => ServerSentCommand::JudgeRunning,
+            Progress::TestDone { done, total } => ServerSentCommand::JudgeTestDone { done, total },
+            Progress::Finished => return,
+        };
+        if let Ok(json) = serde_json::to_string(&command) {
+            let _ = commands.try_send(ConnectionCommands::Send(json));
+        }
+    }
 }
 
 #[derive(Serialize, Debug)]
 #[serde(tag = "t", content = "c")]
 pub enum TestResponse {
-    Error {
-        message: String,
-    },
-    Ran {
-        #[serde(rename = "failedTests")]
-        failed_tests: Vec<FailedTestCase>,
-        #[serde(rename = "okayTests")]
-        okay_tests: Vec<TestCase>,
-    },
-    AllTestsPassed {
-        runtime: u32,
-    },
+    Error { message: String },
+    Ran { results: Vec<TestCaseResult> },
+    AllTestsPassed { runtime: u32 },
+    Cancelled,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -90,6 +162,11 @@ pub struct RoomConfig {
     pub name: String,
     pub public: bool,
     pub owner: User,
+    pub round_duration_secs: u32,
+    pub scoring: Scoring,
+    /// What the problems are picked from. `Unsolved` ones are picked again
+    /// when a round begins.
+    pub filters: Vec<ProblemsFilter>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -148,14 +225,39 @@ pub struct Room {
     chat_messages: VecDeque<ChatMessage>,
     problems: Vec<Problem>,
     round_in_progress: bool,
+    /// The round's time is up, but submissions sent before then are still
+    /// being judged.
+    finishing_round: bool,
+    /// Counts up from 1 as rounds begin.
+    round: u32,
+    round_started_at: DateTime<Utc>,
+    round_ends_at: Option<DateTime<Utc>>,
+    round_timer: Option<JoinHandle<()>>,
     problem_completion: HashMap<UserId, HashSet<u32>>,
+    scoreboard: Scoreboard,
     users_who_finished: u32,
+    problem_repo: ProblemRepo,
+    submission_repo: SubmissionRepo,
+    match_repo: MatchRepo,
+    rating_repo: RatingRepo,
+    // judging id -> the user it's for, what it's for and the task judging it
+    judging: HashMap<u64, (UserId, Judging, JoinHandle<()>)>,
+    next_judging_id: u64,
     pub id: Uuid,
 }
 
 impl Room {
-    pub fn new(id: Uuid, problems: Vec<Problem>, config: RoomConfig) -> Self {
+    pub fn new(
+        id: Uuid,
+        problems: Vec<Problem>,
+        config: RoomConfig,
+        problem_repo: ProblemRepo,
+        submission_repo: SubmissionRepo,
+        match_repo: MatchRepo,
+        rating_repo: RatingRepo,
+    ) -> Self {
         let (commands, commands_rx) = mpsc::channel::<RoomCommands>(200);
+        let scoreboard = Scoreboard::new(config.scoring);
 
         Room {
             commands,
@@ -167,9 +269,21 @@ impl Room {
             chat_messages: Default::default(),
             editor_contents: Default::default(),
             problem_completion: Default::default(),
+            scoreboard,
             users_who_finished: 0,
+            problem_repo,
+            submission_repo,
+            match_repo,
+            rating_repo,
+            judging: Default::default(),
+            next_judging_id: 0,
             problems,
             round_in_progress: false,
+            finishing_round: false,
+            round: 0,
+            round_started_at: Utc::now(),
+            round_ends_at: None,
+            round_timer: None,
             id,
         }
     }
@@ -240,6 +354,8 @@ impl Room {
                             name: self.config.owner.name.clone(),
                             image: self.config.owner.image.clone(),
                         },
+                        round_duration_secs: self.config.round_duration_secs,
+                        scoring: self.config.scoring,
                     },
                 )
                 .await?;
@@ -260,23 +376,17 @@ impl Room {
                 self.editor_contents.insert(user_id.clone(), String::new());
                 self.problem_completion
                     .insert(user_id.clone(), HashSet::default());
+                self.send_all_command(&ServerSentCommand::SetStandings(self.standings()))
+                    .await?;
 
                 if self.round_in_progress {
                     self.send_all_command(&ServerSentCommand::SetProblems(Some(
-                        self.problems
-                            .iter()
-                            .map(|prob| PublicProblem {
-                                difficulty: prob.difficulty,
-                                id: prob.id.clone(),
-                                description: prob.description.clone(),
-                                author: prob.author.clone(),
-                                title: prob.title.clone(),
-                                boilerplate_code: prob.boilerplate_code.clone(),
-                                default_test_cases: prob.test_cases[0..2].to_vec(),
-                            })
-                            .collect(),
+                        self.problems.iter().map(Problem::to_public).collect(),
                     )))
                     .await?;
+                    if let Some(command) = self.round_timer_command() {
+                        self.send_connection(&id, &command).await?;
+                    }
                 }
             }
             RemoveConnection(id) => {
@@ -300,6 +410,8 @@ impl Room {
                         "Trying to remove a problem completion from a nonexistent user."
                     )
                 })?;
+                self.send_all_command(&ServerSentCommand::SetStandings(self.standings()))
+                    .await?;
 
                 self.send_all_command(&ServerSentCommand::SetUsers(
                     self.users
@@ -320,7 +432,7 @@ impl Room {
                 }
             }
             ClientSent(conn_id, data) => {
-                let (user_cmd, user_id) = match self.connections.get(&conn_id) {
+                let (_, user_id) = match self.connections.get(&conn_id) {
                     Some(u) => u,
                     None => return Ok(false),
                 };
@@ -343,26 +455,18 @@ impl Room {
                         .await?;
                     }
                     ClientSentCommand::BeginRound => {
-                        if self.round_in_progress {
+                        if self.round_in_progress || self.finishing_round {
                             return Ok(false);
                         }
-                        self.send_chat_message(ChatMessage::RoundBegin).await?;
-                        self.send_all_command(&ServerSentCommand::SetProblems(Some(
-                            self.problems
-                                .iter()
-                                .map(|prob| PublicProblem {
-                                    difficulty: prob.difficulty,
-                                    id: prob.id.clone(),
-                                    author: prob.author.clone(),
-                                    description: prob.description.clone(),
-                                    title: prob.title.clone(),
-                                    boilerplate_code: prob.boilerplate_code.clone(),
-                                    default_test_cases: prob.test_cases[0..3].to_vec(),
-                                })
-                                .collect(),
-                        )))
-                        .await?;
-                        self.round_in_progress = true;
+                        if let Err(err) = self.pick_unsolved_problems().await {
+                            let message = match err {
+                                RouteErr::Db(message) | RouteErr::Msg(_, message) => message,
+                            };
+                            self.send_connection(&conn_id, &ServerSentCommand::Error(message))
+                                .await?;
+                            return Ok(false);
+                        }
+                        self.begin_round().await?;
                     }
                     ClientSentCommand::SetEditorContent { content } => {
                         self.editor_contents.insert(user_id.clone(), content);
@@ -370,55 +474,51 @@ impl Room {
                     ClientSentCommand::TestCode {
                         test_cases,
                         language,
+                        problem_index,
                     } => {
-                        if !["javascript", "python"].contains(&language.as_str()) {
-                            return Ok(false);
-                        }
-
                         let code = match self.editor_contents.get(user_id) {
-                            Some(d) => d,
+                            Some(d) => d.clone(),
                             None => return Ok(false),
                         };
 
-                        match judge::judge(&language, &code, &test_cases).await {
-                            Err(err) => {
-                                self.send_connection(
-                                    &conn_id,
-                                    &ServerSentCommand::SetTestResponse(TestResponse::Error {
-                                        message: err.to_string(),
-                                    }),
-                                )
-                                .await?;
-                            }
-                            Ok(results) => {
-                                self.send_connection(
-                                    &conn_id,
-                                    &ServerSentCommand::SetTestResponse(TestResponse::Ran {
-                                        failed_tests: results.failed_tests,
-                                        okay_tests: results.okay_tests,
-                                    }),
-                                )
-                                .await?;
-                            }
-                        }
+                        let settings = match problem_index {
+                            Some(index) => match self.problems.get(index as usize) {
+                                Some(problem) => problem.judge_settings(),
+                                None => return Ok(false),
+                            },
+                            None => Settings::default(),
+                        };
+
+                        self.spawn_judge(
+                            &conn_id,
+                            Judging::Test,
+                            language,
+                            code,
+                            test_cases,
+                            settings,
+                        );
                     }
                     ClientSentCommand::SubmitCode {
                         language,
                         problem_index,
                     } => {
-                        if !["javascript", "python"].contains(&language.as_str()) {
+                        let user_id = *user_id;
+                        let username = user.name.clone();
+                        let player = user.to_public();
+
+                        if !self.round_in_progress {
+                            self.send_connection(
+                                &conn_id,
+                                &ServerSentCommand::SetTestResponse(TestResponse::Error {
+                                    message: "No round is in progress.".into(),
+                                }),
+                            )
+                            .await?;
                             return Ok(false);
                         }
 
-                        let user_id = UserId(user.id);
-                        let username = user.name.clone();
-                        self.send_chat_message(ChatMessage::UserSubmitted {
-                            username: username.clone(),
-                        })
-                        .await?;
-
                         let code = match self.editor_contents.get(&user_id) {
-                            Some(d) => d,
+                            Some(d) => d.clone(),
                             None => return Ok(false),
                         };
 
@@ -426,72 +526,65 @@ impl Room {
                             Some(p) => p,
                             None => return Ok(false),
                         };
-                        let test_cases = problem.test_cases.as_slice();
-
-                        match judge::judge(&language, &code, test_cases).await {
-                            Err(err) => {
-                                self.send_connection(
-                                    &conn_id,
-                                    &ServerSentCommand::SetTestResponse(TestResponse::Error {
-                                        message: err.to_string(),
-                                    }),
-                                )
-                                .await?;
-                            }
-                            Ok(results) => {
-                                if results.failed_tests.len() == 0 {
-                                    self.send_connection(
-                                        &conn_id,
-                                        &ServerSentCommand::SetTestResponse(
-                                            TestResponse::AllTestsPassed {
-                                                runtime: results.runtime,
-                                            },
-                                        ),
-                                    )
-                                    .await?;
-
-                                    self.send_chat_message(ChatMessage::UserProblemCompletion {
-                                        username: username.clone(),
-                                        problem_index,
-                                    })
-                                    .await?;
-
-                                    if let Some(completion) =
-                                        self.problem_completion.get_mut(&user_id)
-                                    {
-                                        if completion.len() == self.problems.len() {
-                                            return Ok(false);
-                                        }
-
-                                        completion.insert(problem_index);
-                                        if completion.len() == self.problems.len() {
-                                            self.users_who_finished += 1;
-                                            self.send_chat_message(ChatMessage::UserFinished {
-                                                username: username.clone(),
-                                                place: self.users_who_finished,
-                                            })
-                                            .await?;
-                                        }
-                                    }
-                                } else {
-                                    self.send_connection(
-                                        &conn_id,
-                                        &ServerSentCommand::SetTestResponse(TestResponse::Ran {
-                                            failed_tests: results
-                                                .failed_tests
-                                                .get(0..1)
-                                                .unwrap_or(&[])
-                                                .to_vec(),
-                                            okay_tests: vec![],
-                                        }),
-                                    )
-                                    .await?;
-                                }
+                        let test_cases = problem.test_cases.clone();
+                        let settings = problem.judge_settings();
+
+                        self.scoreboard.add_player(user_id, player);
+                        self.send_chat_message(ChatMessage::UserSubmitted { username })
+                            .await?;
+
+                        self.spawn_judge(
+                            &conn_id,
+                            Judging::Submission {
+                                problem_index,
+                                round: self.round,
+                                submitted_at: Utc::now(),
+                            },
+                            language,
+                            code,
+                            test_cases,
+                            settings,
+                        );
+                    }
+                    ClientSentCommand::CancelJudge => {
+                        let user_id = *user_id;
+                        let mut cancelled = false;
+                        self.judging.retain(|_, (judging_user, _, task)| {
+                            if *judging_user != user_id {
+                                return true;
                             }
+                            // drops the job from the judge queue, or its output
+                            // if it's already running
+                            task.abort();
+                            cancelled = true;
+                            false
+                        });
+
+                        if cancelled {
+                            self.send_judge_response(&conn_id, TestResponse::Cancelled)
+                                .await?;
+                            self.finish_round_if_judged().await?;
                         }
                     }
                 }
             }
+            JudgeFinished(judging_id, user_id, judging, results) => {
+                self.handle_judge_finished(judging_id, user_id, judging, results)
+                    .await?;
+                self.finish_round_if_judged().await?;
+            }
+            RoundTick => {
+                let ends_at = match self.round_ends_at {
+                    Some(ends_at) if self.round_in_progress => ends_at,
+                    _ => return Ok(false),
+                };
+
+                if (ends_at - Utc::now()).num_milliseconds() <= 0 {
+                    self.end_round().await?;
+                } else if let Some(command) = self.round_timer_command() {
+                    self.send_all_command(&command).await?;
+                }
+            }
             Stop => {
                 return Ok(true);
             }
@@ -500,6 +593,129 @@ impl Room {
         Ok(false)
     }
 
+    /// Sends a judging task's results to its user, and records them if they
+    /// are for a submission.
+    async fn handle_judge_finished(
+        &mut self,
+        judging_id: u64,
+        user_id: UserId,
+        judging: Judging,
+        results: anyhow::Result<JudgingResults>,
+    ) -> anyhow::Result<()> {
+        if self.judging.remove(&judging_id).is_none() {
+            // cancelled
+            return Ok(());
+        }
+
+        // `None` if they left before their code was judged. Their submissions
+        // still count, so they can't leave to avoid losing rating
+        let connection = self
+            .users
+            .get(&user_id)
+            .map(|(conn_id, user)| (*conn_id, user.name.clone()));
+
+        let results = match results {
+            Ok(results) => results,
+            Err(err) => {
+                if let Some((conn_id, _)) = &connection {
+                    self.send_judge_response(
+                        conn_id,
+                        TestResponse::Error {
+                            message: err.to_string(),
+                        },
+                    )
+                    .await?;
+                }
+                return Ok(());
+            }
+        };
+
+        let (problem_index, round, submitted_at) = match judging {
+            Judging::Test => {
+                if let Some((conn_id, _)) = &connection {
+                    self.send_judge_response(
+                        conn_id,
+                        TestResponse::Ran {
+                            results: results.results,
+                        },
+                    )
+                    .await?;
+                }
+                return Ok(());
+            }
+            Judging::Submission {
+                problem_index,
+                round,
+                submitted_at,
+            } => (problem_index, round, submitted_at),
+        };
+
+        let problem = match self.problems.get(problem_index as usize) {
+            Some(problem) => problem,
+            None => return Ok(()),
+        };
+        let score = problem.score(&results);
+        let response = if results.all_passed() {
+            TestResponse::AllTestsPassed {
+                runtime: results.runtime,
+            }
+        } else {
+            TestResponse::Ran {
+                results: results.visible(&problem.test_cases),
+            }
+        };
+        if let Some((conn_id, _)) = &connection {
+            self.send_judge_response(conn_id, response).await?;
+        }
+
+        // from a round that is already over
+        if round != self.round || !(self.round_in_progress || self.finishing_round) {
+            return Ok(());
+        }
+
+        self.scoreboard.record(
+            user_id,
+            problem_index,
+            (submitted_at - self.round_started_at)
+                .to_std()
+                .unwrap_or_default(),
+            score,
+            results.all_passed(),
+        );
+        self.send_all_command(&ServerSentCommand::SetStandings(self.standings()))
+            .await?;
+
+        let username = match connection {
+            Some((_, username)) => username,
+            None => return Ok(()),
+        };
+        if results.all_passed() {
+            self.send_chat_message(ChatMessage::UserProblemCompletion {
+                username: username.clone(),
+                problem_index,
+            })
+            .await?;
+
+            if let Some(completion) = self.problem_completion.get_mut(&user_id) {
+                if completion.len() == self.problems.len() {
+                    return Ok(());
+                }
+
+                completion.insert(problem_index);
+                if completion.len() == self.problems.len() {
+                    self.users_who_finished += 1;
+                    self.send_chat_message(ChatMessage::UserFinished {
+                        username: username.clone(),
+                        place: self.users_who_finished,
+                    })
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     async fn send_all_command(&mut self, command: &ServerSentCommand) -> anyhow::Result<()> {
         let data = serde_json::to_string(command)?;
 
@@ -525,6 +741,293 @@ impl Room {
         }
     }
 
+    /// Picks the problems again if any filter is `Unsolved`, leaving out
+    /// problems solved by anyone in the room. Fails, leaving the problems as
+    /// they were, if a filter matches none.
+    async fn pick_unsolved_problems(&mut self) -> Result<(), RouteErr> {
+        if !self
+            .config
+            .filters
+            .iter()
+            .any(|filter| matches!(filter, ProblemsFilter::Unsolved { .. }))
+        {
+            return Ok(());
+        }
+
+        let user_ids = self
+            .users
+            .keys()
+            .map(|user_id| user_id.0)
+            .collect::<Vec<_>>();
+        let solved = self
+            .submission_repo
+            .get_solved_problem_ids(&user_ids)
+            .await?;
+        self.problems = self
+            .problem_repo
+            .get_from_filters(&self.config.filters, &solved)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn begin_round(&mut self) -> anyhow::Result<()> {
+        self.round += 1;
+        self.round_in_progress = true;
+        self.users_who_finished = 0;
+        self.problem_completion
+            .values_mut()
+            .for_each(|completion| completion.clear());
+        self.scoreboard.reset();
+
+        self.round_started_at = Utc::now();
+        let ends_at = self.round_started_at
+            + chrono::Duration::seconds(self.config.round_duration_secs as i64);
+        self.round_ends_at = Some(ends_at);
+        self.start_round_timer(ends_at);
+
+        self.send_chat_message(ChatMessage::RoundBegin).await?;
+        self.send_all_command(&ServerSentCommand::SetProblems(Some(
+            self.problems.iter().map(Problem::to_public).collect(),
+        )))
+        .await?;
+        self.send_all_command(&ServerSentCommand::SetStandings(self.standings()))
+            .await?;
+        if let Some(command) = self.round_timer_command() {
+            self.send_all_command(&command).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn end_round(&mut self) -> anyhow::Result<()> {
+        self.round_in_progress = false;
+        if let Some(timer) = self.round_timer.take() {
+            timer.abort();
+        }
+
+        // submissions sent in time still count, so the round is only over
+        // once they have been judged
+        self.finishing_round = true;
+        self.finish_round_if_judged().await
+    }
+
+    /// Ends the round if its time is up and every submission sent before then
+    /// has been judged, then records it.
+    async fn finish_round_if_judged(&mut self) -> anyhow::Result<()> {
+        let round = self.round;
+        let judging = self.judging.values().any(|(_, judging, _)| {
+            matches!(judging, Judging::Submission { round: judged, .. } if *judged == round)
+        });
+        if !self.finishing_round || judging {
+            return Ok(());
+        }
+        self.finishing_round = false;
+
+        let standings = self.final_standings();
+        self.save_match(&standings);
+        self.round_ends_at = None;
+
+        self.send_chat_message(ChatMessage::RoundEnd).await?;
+        self.send_all_command(&ServerSentCommand::RoundEnd { standings })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records the round that just ended and rates its players, in the
+    /// background.
+    fn save_match(&self, standings: &[Standing]) {
+        if standings.is_empty() {
+            return;
+        }
+
+        let ended_at = self.round_ends_at.unwrap_or_else(Utc::now);
+        let data = CreateMatch {
+            room_id: self.id,
+            room_name: self.config.name.clone(),
+            round: self.round,
+            scoring: self.config.scoring,
+            problems: self.problems.iter().map(Into::into).collect(),
+            standings: standings.to_vec(),
+            user_ids: standings.iter().map(|standing| standing.user.id).collect(),
+            started_at: self.round_started_at,
+            ended_at,
+            duration_secs: (ended_at - self.round_started_at).num_seconds().max(0) as u32,
+        };
+        let match_repo = self.match_repo.clone();
+        let rating_repo = self.rating_repo.clone();
+        tokio::spawn(async move {
+            let match_id = match match_repo.create(&data).await {
+                Ok(match_id) => match_id,
+                Err(err) => {
+                    log::error!("Error saving match: {:?}", err);
+                    return;
+                }
+            };
+            if let Err(err) = rating_repo.rate_match(&match_id, &data.standings).await {
+                log::error!("Error rating match: {:?}", err);
+            }
+        });
+    }
+
+    fn round_timer_command(&self) -> Option<ServerSentCommand> {
+        let ends_at = self.round_ends_at?;
+        let millis_left = (ends_at - Utc::now()).num_milliseconds().max(0);
+
+        Some(ServerSentCommand::SetRoundTimer {
+            ends_at,
+            // rounded up, so it only reaches 0 when the round is over
+            seconds_left: (millis_left + 999) / 1000,
+        })
+    }
+
+    /// Sends `RoundTick` every minute, then every second for the last ten
+    /// seconds, and once more when the round is over.
+    fn start_round_timer(&mut self, ends_at: DateTime<Utc>) {
+        if let Some(timer) = self.round_timer.take() {
+            timer.abort();
+        }
+
+        let commands = self.commands.clone();
+        self.round_timer = Some(tokio::spawn(async move {
+            loop {
+                let millis_left = (ends_at - Utc::now()).num_milliseconds();
+                if millis_left <= 0 {
+                    break;
+                }
+
+                let seconds_left = (millis_left + 999) / 1000;
+                let next_tick = match seconds_left {
+                    61.. => (seconds_left - 1) / 60 * 60,
+                    11..=60 => 10,
+                    _ => seconds_left - 1,
+                };
+                time::sleep(Duration::from_millis(
+                    (millis_left - next_tick * 1000) as u64,
+                ))
+                .await;
+
+                if commands.send(RoomCommands::RoundTick).await.is_err() {
+                    return;
+                }
+            }
+
+            // the last sleep can end a little early, so make sure the round ends
+            let _ = commands.send(RoomCommands::RoundTick).await;
+        }));
+    }
+
+    fn standings(&self) -> Vec<Standing> {
+        self.scoreboard.standings(
+            self.users
+                .iter()
+                .map(|(user_id, (_, user))| (*user_id, user.to_public()))
+                .collect(),
+            self.problems.len() as u32,
+        )
+    }
+
+    /// Everyone in the room, and everyone who submitted anything this round
+    /// but has since left.
+    fn final_standings(&self) -> Vec<Standing> {
+        let mut users = self.scoreboard.players().collect::<HashMap<_, _>>();
+        users.extend(
+            self.users
+                .iter()
+                .map(|(user_id, (_, user))| (*user_id, user.to_public())),
+        );
+
+        self.scoreboard
+            .standings(users.into_iter().collect(), self.problems.len() as u32)
+    }
+
+    /// Judges in the background so the room keeps handling commands meanwhile,
+    /// reporting back with `RoomCommands::JudgeFinished`.
+    fn spawn_judge(
+        &mut self,
+        conn_id: &ConnId,
+        judging: Judging,
+        language: &'static Language,
+        code: String,
+        test_cases: Vec<TestCase>,
+        settings: Settings,
+    ) {
+        let (connection, user_id) = match self.connections.get(conn_id) {
+            Some((connection, user_id)) => (connection.clone(), *user_id),
+            None => return,
+        };
+        let submitter = Submitter {
+            user: user_id.0,
+            room: Some(self.id),
+            priority: match judging {
+                Judging::Test => Priority::Test,
+                Judging::Submission { .. } => Priority::Submission,
+            },
+        };
+        let record = match judging {
+            Judging::Test => None,
+            Judging::Submission { problem_index, .. } => self
+                .problems
+                .get(problem_index as usize)
+                .map(|problem| (self.submission_repo.clone(), problem.clone())),
+        };
+        let room_id = self.id;
+        let commands = self.commands.clone();
+        let judging_id = self.next_judging_id;
+        self.next_judging_id += 1;
+
+        let task = tokio::spawn(async move {
+            let progress = report_progress(connection);
+            let results = judge::judge(
+                language,
+                &code,
+                &test_cases,
+                &settings,
+                &submitter,
+                &progress,
+            )
+            .await;
+
+            if let (Some((submission_repo, problem)), Ok(results)) = (record, &results) {
+                let submission = CreateSubmission::new(
+                    user_id.0,
+                    &problem,
+                    Some(room_id),
+                    language,
+                    code,
+                    results,
+                );
+                if let Err(err) = submission_repo.create(&submission).await {
+                    log::error!("Error saving submission: {:?}", err);
+                }
+            }
+
+            if let Err(err) = commands
+                .send(RoomCommands::JudgeFinished(
+                    judging_id, user_id, judging, results,
+                ))
+                .await
+            {
+                log::error!("Error reporting judging results: {}", err);
+            }
+        });
+        self.judging.insert(judging_id, (user_id, judging, task));
+    }
+
+    /// Sends the result of a judging request, then `JudgeFinished` so the
+    /// client knows nothing more is coming for it.
+    async fn send_judge_response(
+        &mut self,
+        conn_id: &ConnId,
+        response: TestResponse,
+    ) -> anyhow::Result<()> {
+        self.send_connection(conn_id, &ServerSentCommand::SetTestResponse(response))
+            .await?;
+        self.send_connection(conn_id, &ServerSentCommand::JudgeFinished)
+            .await
+    }
+
     fn cancel_deletion(&mut self) {
         if let Some(task) = self.deletion_timer.take() {
             task.abort();
@@ -0,0 +1,214 @@
+use std::{cmp::Ordering, collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::user::PublicUser;
+
+use super::room::UserId;
+
+/// Minutes added to a solved problem's time for each wrong attempt before it.
+const PENALTY_PER_WRONG_ATTEMPT_MINUTES: u64 = 20;
+
+/// How a user has done on one problem in the current round.
+#[derive(Debug, Clone, Default)]
+struct ProblemRecord {
+    attempts: u32,
+    /// Since the round began, when the accepted submission was sent.
+    solved_after: Option<Duration>,
+    /// The best score of any attempt.
+    score: u32,
+}
+
+impl ProblemRecord {
+    fn penalty_minutes(&self) -> u64 {
+        match self.solved_after {
+            Some(solved_after) => {
+                solved_after.as_secs() / 60
+                    + (self.attempts as u64 - 1) * PENALTY_PER_WRONG_ATTEMPT_MINUTES
+            }
+            None => 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProblemStanding {
+    pub attempts: u32,
+    /// Seconds from the start of the round.
+    pub solve_time_secs: Option<u64>,
+    pub score: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Standing {
+    /// From 1. Users who are tied share a rank.
+    pub rank: u32,
+    pub user: PublicUser,
+    /// Indices of the problems solved.
+    pub solved: Vec<u32>,
+    pub score: u32,
+    /// Minutes, from solve times and wrong attempts.
+    pub penalty: u64,
+    /// For each problem, in order.
+    pub problems: Vec<ProblemStanding>,
+}
+
+/// How users in a room are ranked, picked when the room is created.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scoring {
+    /// Most problems solved, then the least penalty: minutes from the start
+    /// of the round to each solve, plus 20 for each wrong attempt before it.
+    Icpc,
+    /// Most points, summing the best score on each problem, so partially
+    /// solving a problem counts.
+    Ioi,
+    /// Most problems solved, then whoever solved their last one first, so
+    /// users who finish are ranked in the order they finished.
+    #[default]
+    Race,
+}
+
+impl Scoring {
+    /// `Less` if `a` ranks above `b`, `Equal` if they're tied.
+    fn compare(&self, a: &Standing, b: &Standing) -> Ordering {
+        match self {
+            Scoring::Icpc => b
+                .solved
+                .len()
+                .cmp(&a.solved.len())
+                .then(a.penalty.cmp(&b.penalty)),
+            Scoring::Ioi => b.score.cmp(&a.score),
+            Scoring::Race => b
+                .solved
+                .len()
+                .cmp(&a.solved.len())
+                .then(a.last_solve_time_secs().cmp(&b.last_solve_time_secs())),
+        }
+    }
+}
+
+/// Every user's submissions in the current round, for ranking them. Records
+/// are kept when users leave, so they are back in the standings if they
+/// rejoin, and still count when the round ends.
+#[derive(Debug)]
+pub struct Scoreboard {
+    scoring: Scoring,
+    records: HashMap<UserId, HashMap<u32, ProblemRecord>>,
+    /// Everyone who submitted anything in the current round.
+    players: HashMap<UserId, PublicUser>,
+}
+
+impl Scoreboard {
+    pub fn new(scoring: Scoring) -> Self {
+        Self {
+            scoring,
+            records: Default::default(),
+            players: Default::default(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.records.clear();
+        self.players.clear();
+    }
+
+    /// Called when a user submits, before the submission is judged.
+    pub fn add_player(&mut self, user_id: UserId, user: PublicUser) {
+        self.players.insert(user_id, user);
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = (UserId, PublicUser)> + '_ {
+        self.players
+            .iter()
+            .map(|(user_id, user)| (*user_id, user.clone()))
+    }
+
+    /// Records a judged submission, sent `elapsed` after the round began.
+    /// Submissions to a problem already solved change nothing.
+    pub fn record(
+        &mut self,
+        user_id: UserId,
+        problem_index: u32,
+        elapsed: Duration,
+        score: u32,
+        passed: bool,
+    ) {
+        let record = self
+            .records
+            .entry(user_id)
+            .or_default()
+            .entry(problem_index)
+            .or_default();
+        if record.solved_after.is_some() {
+            return;
+        }
+
+        record.attempts += 1;
+        record.score = record.score.max(score);
+        if passed {
+            record.solved_after = Some(elapsed);
+        }
+    }
+
+    /// Ranks `users` by the room's scoring.
+    pub fn standings(&self, users: Vec<(UserId, PublicUser)>, problems: u32) -> Vec<Standing> {
+        let no_records = HashMap::new();
+        let mut standings = users
+            .into_iter()
+            .map(|(user_id, user)| {
+                let records = self.records.get(&user_id).unwrap_or(&no_records);
+                let problems = (0..problems)
+                    .map(|index| records.get(&index).cloned().unwrap_or_default())
+                    .collect::<Vec<_>>();
+
+                Standing {
+                    rank: 0,
+                    user,
+                    solved: (0..)
+                        .zip(&problems)
+                        .filter(|(_, record)| record.solved_after.is_some())
+                        .map(|(index, _)| index)
+                        .collect(),
+                    score: problems.iter().map(|record| record.score).sum(),
+                    penalty: problems.iter().map(ProblemRecord::penalty_minutes).sum(),
+                    problems: problems
+                        .iter()
+                        .map(|record| ProblemStanding {
+                            attempts: record.attempts,
+                            solve_time_secs: record.solved_after.map(|after| after.as_secs()),
+                            score: record.score,
+                        })
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        standings.sort_by(|a, b| self.scoring.compare(a, b));
+
+        for index in 0..standings.len() {
+            standings[index].rank = if index > 0
+                && self
+                    .scoring
+                    .compare(&standings[index - 1], &standings[index])
+                    .is_eq()
+            {
+                standings[index - 1].rank
+            } else {
+                index as u32 + 1
+            };
+        }
+
+        standings
+    }
+}
+
+impl Standing {
+    fn last_solve_time_secs(&self) -> Option<u64> {
+        self.problems
+            .iter()
+            .filter_map(|problem| problem.solve_time_secs)
+            .max()
+    }
+}
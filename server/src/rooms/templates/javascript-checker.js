@@ -0,0 +1,13 @@
+// RADIX CHECKER STUFF -- DO NOT TOUCH
+
+const __RADIX_CHECKS = {{INPUTS}};
+
+for (let index = 0; index < __RADIX_CHECKS.length; index++) {
+  console.log(
+    "\n[[RADIX TEST OUTPUT]]",
+    JSON.stringify({
+      index,
+      accepted: Boolean(check(...__RADIX_CHECKS[index])),
+    })
+  );
+}
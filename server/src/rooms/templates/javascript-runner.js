@@ -0,0 +1,25 @@
+// RADIX TEST STUFF -- DO NOT TOUCH
+
+const __RADIX_TEST_INPUTS = {{INPUTS}};
+
+console.log("\n[[RADIX TESTS STARTED]]");
+
+for (let index = 0; index < __RADIX_TEST_INPUTS.length; index++) {
+  const startTime = performance.now();
+
+  let result;
+  try {
+    const output = solve(...__RADIX_TEST_INPUTS[index]);
+    result = { output: output === undefined ? null : output };
+    JSON.stringify(result);
+  } catch (err) {
+    result = { error: err instanceof Error ? err.stack : String(err) };
+  }
+
+  const endTime = performance.now();
+
+  result.index = index;
+  result.runtime = Math.round(endTime - startTime);
+
+  console.log("\n[[RADIX TEST OUTPUT]]", JSON.stringify(result));
+}
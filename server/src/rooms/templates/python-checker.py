@@ -0,0 +1,19 @@
+# RADIX CHECKER STUFF -- DO NOT TOUCH
+
+import json
+
+__RADIX_CHECKS = json.loads({{INPUTS}})
+
+
+for __radix_index, __radix_check in enumerate(__RADIX_CHECKS):
+    print(
+        "\n[[RADIX TEST OUTPUT]]",
+        json.dumps(
+            {
+                "index": __radix_index,
+                "accepted": bool(check(*__radix_check)),
+            },
+            separators=(",", ":"),
+        ),
+        flush=True,
+    )
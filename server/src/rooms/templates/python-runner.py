@@ -1,25 +1,29 @@
 # RADIX TEST STUFF -- DO NOT TOUCH
 
-import json, time
+import json, time, traceback
 
-__RADIX_TEST_INPUTS = json.loads("{{INPUTS}}")
-output = []
+__RADIX_TEST_INPUTS = json.loads({{INPUTS}})
 
+print("\n[[RADIX TESTS STARTED]]", flush=True)
 
-start_time = time.time_ns() // 1_000_000
+for __radix_index, __radix_input in enumerate(__RADIX_TEST_INPUTS):
+    __radix_start_time = time.perf_counter_ns()
 
-for input in __RADIX_TEST_INPUTS:
-    output.append(solve(*input))
+    try:
+        __radix_result = {"output": solve(*__radix_input)}
+        json.dumps(__radix_result)
+    except MemoryError:
+        __radix_result = {"memoryExceeded": True}
+    except BaseException:
+        __radix_result = {"error": traceback.format_exc()}
 
-end_time = time.time_ns() // 1_000_000
+    __radix_end_time = time.perf_counter_ns()
 
-print(
-    "[[RADIX TEST OUTPUT]]",
-    json.dumps(
-        {
-            "runtime": end_time - start_time,
-            "program_output": output,
-        },
-        separators=(",", ":"),
-    ),
-)
+    __radix_result["index"] = __radix_index
+    __radix_result["runtime"] = (__radix_end_time - __radix_start_time) // 1_000_000
+
+    print(
+        "\n[[RADIX TEST OUTPUT]]",
+        json.dumps(__radix_result, separators=(",", ":")),
+        flush=True,
+    )
@@ -0,0 +1,42 @@
+use axum::{
+    extract::{Path, Query},
+    routing::get,
+    Json, Router,
+};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use crate::{
+    errors::RouteErr,
+    models::matches::{Match, MatchRepo},
+    mongo::ToObjectId,
+    AppState,
+};
+
+pub fn match_routes() -> Router<AppState> {
+    Router::new()
+        .route("/user/:user_id", get(get_for_user))
+        .route("/:id", get(get_by_id))
+}
+
+#[derive(Deserialize)]
+struct Pagination {
+    cursor: Option<ObjectId>,
+}
+
+/// A user's past matches, newest first.
+async fn get_for_user(
+    Path(user_id): Path<String>,
+    Query(query): Query<Pagination>,
+    match_repo: MatchRepo,
+) -> Result<Json<Vec<Match>>, RouteErr> {
+    let matches = match_repo
+        .get_for_user(&user_id.to_object_id()?, query.cursor)
+        .await?;
+    Ok(Json(matches))
+}
+
+async fn get_by_id(Path(id): Path<String>, match_repo: MatchRepo) -> Result<Json<Match>, RouteErr> {
+    let found = match_repo.get_by_id(&id.to_object_id()?).await?;
+    Ok(Json(found))
+}
@@ -1,3 +1,6 @@
 pub mod auth;
+pub mod matches;
 pub mod problems;
+pub mod ratings;
 pub mod rooms;
+pub mod submissions;
@@ -8,11 +8,25 @@ use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    errors::RouteErr,
+    errors::{ConvertResult, RouteErr},
     models::{
-        problem::{ListingProblem, Problem, ProblemRepo, UpdateProblem},
+        problem::{
+            ListingProblem, Problem, ProblemRepo, ProblemStatus, SearchProblems, SearchResults,
+            TestCase, UpdateProblem,
+        },
+        submission::{CreateSubmission, SubmissionRepo},
         user::User,
     },
+    mongo::ToObjectId,
+    rooms::{
+        judge::{
+            self,
+            checker::Checker,
+            queue::{Priority, Submitter},
+            JudgingResults, TestCaseResult, Verdict,
+        },
+        languages::{self, Language},
+    },
     AppState,
 };
 
@@ -21,6 +35,12 @@ pub fn problem_routes() -> Router<AppState> {
         .route("/", post(create_problem))
         .route("/:id", get(get_by_id))
         .route("/:id", put(update_problem))
+        .route("/:id/test", post(test_code))
+        .route("/:id/submit", post(submit_code))
+        .route("/:id/review", post(request_review))
+        .route("/:id/publish", post(publish))
+        .route("/:id/unpublish", post(unpublish))
+        .route("/:id/archive", post(archive))
         .route("/infinite", get(get_infinite))
         .route("/search", get(search))
 }
@@ -75,9 +95,13 @@ async fn get_by_id(
         .map_err(|_| RouteErr::Msg(StatusCode::BAD_REQUEST, "Invalid id.".into()))?;
     let problem = problem_repo.get_by_id(&problem_id).await?;
 
-    if let Some(mut problem) = problem {
+    if let Some(mut problem) = problem.filter(|problem| problem.is_visible_to(&user.id)) {
         if problem.author.id != user.id {
-            problem.test_cases.truncate(5);
+            problem.test_cases.retain(|test_case| test_case.sample);
+            problem.solution = None;
+            if let Some(Checker::Custom { code, .. }) = &mut problem.checker {
+                code.clear();
+            }
         }
 
         return Ok(Json(problem));
@@ -89,15 +113,220 @@ async fn get_by_id(
     ))
 }
 
+async fn search(
+    problem_repo: ProblemRepo,
+    Query(query): Query<SearchProblems>,
+) -> Result<Json<SearchResults>, RouteErr> {
+    let results = problem_repo.search(&query).await?;
+    Ok(Json(results))
+}
+
 #[derive(Deserialize)]
-struct Search {
-    query: String,
+#[serde(rename_all = "camelCase")]
+struct TestCode {
+    #[serde(with = "languages::by_name")]
+    language: &'static Language,
+    code: String,
+    test_cases: Vec<TestCase>,
 }
 
-async fn search(
+/// Runs code against the user's own test cases, with the problem's limits,
+/// checker and I/O mode. Nothing is recorded.
+async fn test_code(
+    user: User,
+    Path(problem_id): Path<String>,
     problem_repo: ProblemRepo,
-    Query(query): Query<Search>,
-) -> Result<Json<Vec<ListingProblem>>, RouteErr> {
-    let problems = problem_repo.search(&query.query).await?;
-    Ok(Json(problems))
+    Json(data): Json<TestCode>,
+) -> Result<Json<JudgingResults>, RouteErr> {
+    let problem = problem_repo
+        .get_by_id(&problem_id.to_object_id()?)
+        .await?
+        .filter(|problem| problem.is_visible_to(&user.id))
+        .convert(Some("Problem not found."))?;
+
+    let submitter = Submitter {
+        user: user.id,
+        room: None,
+        priority: Priority::Test,
+    };
+    let results = judge::judge(
+        data.language,
+        &data.code,
+        &data.test_cases,
+        &problem.judge_settings(),
+        &submitter,
+        &|_| (),
+    )
+    .await
+    .map_err(|err| RouteErr::Msg(StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    Ok(Json(results))
+}
+
+#[derive(Deserialize)]
+struct SubmitCode {
+    #[serde(with = "languages::by_name")]
+    language: &'static Language,
+    code: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmitResult {
+    /// Of the recorded submission.
+    id: String,
+    passed: bool,
+    score: u32,
+    runtime: u32,
+    results: Vec<TestCaseResult>,
+}
+
+/// Judges code against all of a problem's test cases outside of a room, and
+/// records it like a submission in a room.
+async fn submit_code(
+    user: User,
+    Path(problem_id): Path<String>,
+    problem_repo: ProblemRepo,
+    submission_repo: SubmissionRepo,
+    Json(data): Json<SubmitCode>,
+) -> Result<Json<SubmitResult>, RouteErr> {
+    let problem = problem_repo
+        .get_by_id(&problem_id.to_object_id()?)
+        .await?
+        .filter(|problem| problem.is_visible_to(&user.id))
+        .convert(Some("Problem not found."))?;
+
+    let submitter = Submitter {
+        user: user.id,
+        room: None,
+        priority: Priority::Submission,
+    };
+    let results = judge::judge(
+        data.language,
+        &data.code,
+        &problem.test_cases,
+        &problem.judge_settings(),
+        &submitter,
+        &|_| (),
+    )
+    .await
+    .map_err(|err| RouteErr::Msg(StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    let id = submission_repo
+        .create(&CreateSubmission::new(
+            user.id,
+            &problem,
+            None,
+            data.language,
+            data.code,
+            &results,
+        ))
+        .await?;
+
+    Ok(Json(SubmitResult {
+        id: id.to_string(),
+        passed: results.all_passed(),
+        score: problem.score(&results),
+        runtime: results.runtime,
+        results: results.visible(&problem.test_cases),
+    }))
+}
+
+/// Marks a draft as ready for others to look over.
+async fn request_review(
+    user: User,
+    Path(problem_id): Path<String>,
+    problem_repo: ProblemRepo,
+) -> Result<(), RouteErr> {
+    let problem_id = problem_id.to_object_id()?;
+    let problem = problem_repo
+        .get_by_id(&problem_id)
+        .await?
+        .filter(|problem| problem.author.id == user.id)
+        .convert(Some("Problem not found."))?;
+    if problem.status != ProblemStatus::Draft {
+        return Err(RouteErr::Msg(
+            StatusCode::BAD_REQUEST,
+            "Only drafts can be sent for review.".into(),
+        ));
+    }
+
+    problem_repo
+        .set_status(&problem_id, &user.id, ProblemStatus::InReview)
+        .await
+}
+
+/// Publishes a problem once it's complete and its solution passes all of its
+/// test cases.
+async fn publish(
+    user: User,
+    Path(problem_id): Path<String>,
+    problem_repo: ProblemRepo,
+) -> Result<(), RouteErr> {
+    let problem_id = problem_id.to_object_id()?;
+    let problem = problem_repo
+        .get_by_id(&problem_id)
+        .await?
+        .filter(|problem| problem.author.id == user.id)
+        .convert(Some("Problem not found."))?;
+    if problem.status == ProblemStatus::Published {
+        return Ok(());
+    }
+
+    let solution = problem.validate_for_publishing()?;
+    let submitter = Submitter {
+        user: user.id,
+        room: None,
+        priority: Priority::Submission,
+    };
+    let results = judge::judge(
+        solution.language,
+        &solution.code,
+        &problem.test_cases,
+        &problem.judge_settings(),
+        &submitter,
+        &|_| (),
+    )
+    .await
+    .map_err(|err| RouteErr::Msg(StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    if let Some(failed) = results
+        .results
+        .iter()
+        .position(|result| !matches!(result.verdict, Verdict::Accepted))
+    {
+        return Err(RouteErr::Msg(
+            StatusCode::BAD_REQUEST,
+            format!("The solution fails test case {}.", failed + 1),
+        ));
+    }
+
+    problem_repo
+        .publish(&problem_id, &user.id, problem.revision)
+        .await
+}
+
+/// Takes a problem back to being a draft.
+async fn unpublish(
+    user: User,
+    Path(problem_id): Path<String>,
+    problem_repo: ProblemRepo,
+) -> Result<(), RouteErr> {
+    problem_repo
+        .set_status(&problem_id.to_object_id()?, &user.id, ProblemStatus::Draft)
+        .await
+}
+
+async fn archive(
+    user: User,
+    Path(problem_id): Path<String>,
+    problem_repo: ProblemRepo,
+) -> Result<(), RouteErr> {
+    problem_repo
+        .set_status(
+            &problem_id.to_object_id()?,
+            &user.id,
+            ProblemStatus::Archived,
+        )
+        .await
 }
@@ -0,0 +1,28 @@
+use axum::{extract::Path, routing::get, Json, Router};
+
+use crate::{
+    errors::RouteErr,
+    models::rating::{LeaderboardEntry, RatingChange, RatingRepo},
+    mongo::ToObjectId,
+    AppState,
+};
+
+pub fn rating_routes() -> Router<AppState> {
+    Router::new()
+        .route("/leaderboard", get(get_leaderboard))
+        .route("/history/:user_id", get(get_history))
+}
+
+async fn get_leaderboard(rating_repo: RatingRepo) -> Result<Json<Vec<LeaderboardEntry>>, RouteErr> {
+    let leaderboard = rating_repo.get_leaderboard().await?;
+    Ok(Json(leaderboard))
+}
+
+/// A user's rating changes, newest first.
+async fn get_history(
+    Path(user_id): Path<String>,
+    rating_repo: RatingRepo,
+) -> Result<Json<Vec<RatingChange>>, RouteErr> {
+    let history = rating_repo.get_history(&user_id.to_object_id()?).await?;
+    Ok(Json(history))
+}
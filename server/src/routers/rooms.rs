@@ -9,16 +9,21 @@ use futures_util::SinkExt;
 use mongodb::bson::Uuid;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use std::{fmt, ops::RangeInclusive};
 
 use crate::{
     errors::RouteErr,
     models::{
+        matches::MatchRepo,
         problem::ProblemRepo,
+        rating::RatingRepo,
+        submission::SubmissionRepo,
         user::{PublicUser, User},
     },
     rooms::{
         connection::Connection,
         room::{Room, RoomConfig, ServerSentCommand},
+        standings::Scoring,
     },
     AppState,
 };
@@ -37,19 +42,98 @@ struct CreateRoom {
     pub name: String,
     pub public: bool,
     pub problems: Vec<ProblemsFilter>,
+    #[serde(default = "default_round_duration_secs")]
+    pub round_duration_secs: u32,
+    #[serde(default)]
+    pub scoring: Scoring,
 }
 
-#[derive(Deserialize, Debug)]
+fn default_round_duration_secs() -> u32 {
+    30 * 60
+}
+
+const ROUND_DURATION_RANGE_SECS: RangeInclusive<u32> = 60..=6 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase", tag = "t", content = "c")]
 pub enum ProblemsFilter {
     #[serde(rename = "Single")]
     Single { id: String },
-    // TODO: the rest of the filters
+    #[serde(rename = "Random")]
+    Random { count: u32 },
+    /// Difficulties from `min` to `max`, inclusive.
+    #[serde(rename = "DifficultyRange")]
+    DifficultyRange { min: u8, max: u8, count: u32 },
+    #[serde(rename = "ByTag")]
+    ByTag { tag: String, count: u32 },
+    #[serde(rename = "ByAuthor", rename_all = "camelCase")]
+    ByAuthor { author_id: String, count: u32 },
+    /// Problems nobody in the room has solved, picked again whenever a round
+    /// begins.
+    #[serde(rename = "Unsolved")]
+    Unsolved { count: u32 },
+}
+
+impl fmt::Display for ProblemsFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProblemsFilter::Single { id } => write!(f, "problem {}", id),
+            ProblemsFilter::Random { count } => write!(f, "{} random", count),
+            ProblemsFilter::DifficultyRange { min, max, count } => {
+                write!(f, "{} of difficulty {} to {}", count, min, max)
+            }
+            ProblemsFilter::ByTag { tag, count } => write!(f, "{} tagged \"{}\"", count, tag),
+            ProblemsFilter::ByAuthor { author_id, count } => {
+                write!(f, "{} by author {}", count, author_id)
+            }
+            ProblemsFilter::Unsolved { count } => write!(f, "{} unsolved", count),
+        }
+    }
+}
+
+/// How many problems a filter other than `Single` can pick.
+const FILTER_COUNT_RANGE: RangeInclusive<u32> = 1..=10;
+/// How many filters a room can have.
+const MAX_FILTERS: usize = 20;
+
+impl ProblemsFilter {
+    fn validate(&self) -> Result<(), RouteErr> {
+        let count = match self {
+            ProblemsFilter::Single { .. } => return Ok(()),
+            ProblemsFilter::DifficultyRange { min, max, .. } if min > max => {
+                return Err(RouteErr::Msg(
+                    StatusCode::BAD_REQUEST,
+                    "Difficulty range minimum is more than its maximum.".into(),
+                ));
+            }
+            ProblemsFilter::Random { count }
+            | ProblemsFilter::DifficultyRange { count, .. }
+            | ProblemsFilter::ByTag { count, .. }
+            | ProblemsFilter::ByAuthor { count, .. }
+            | ProblemsFilter::Unsolved { count } => *count,
+        };
+
+        if !FILTER_COUNT_RANGE.contains(&count) {
+            return Err(RouteErr::Msg(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Problem filters must pick between {} and {} problems.",
+                    FILTER_COUNT_RANGE.start(),
+                    FILTER_COUNT_RANGE.end()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 async fn create_room(
     owner: User,
     problem_repo: ProblemRepo,
+    submission_repo: SubmissionRepo,
+    match_repo: MatchRepo,
+    rating_repo: RatingRepo,
     State(state): State<AppState>,
     Json(data): Json<CreateRoom>,
 ) -> Result<(), RouteErr> {
@@ -67,18 +151,63 @@ async fn create_room(
         ));
     }
 
+    if !ROUND_DURATION_RANGE_SECS.contains(&data.round_duration_secs) {
+        return Err(RouteErr::Msg(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Round duration must be between {} and {} minutes.",
+                ROUND_DURATION_RANGE_SECS.start() / 60,
+                ROUND_DURATION_RANGE_SECS.end() / 60
+            ),
+        ));
+    }
+
+    if data.problems.is_empty() {
+        return Err(RouteErr::Msg(
+            StatusCode::BAD_REQUEST,
+            "Rooms need at least one problem filter.".into(),
+        ));
+    }
+    if data.problems.len() > MAX_FILTERS {
+        return Err(RouteErr::Msg(
+            StatusCode::BAD_REQUEST,
+            format!("Rooms can have at most {} problem filters.", MAX_FILTERS),
+        ));
+    }
+    for filter in &data.problems {
+        filter.validate()?;
+    }
+
     let id = Uuid::new();
     let config = RoomConfig {
         name: data.name.clone(),
         public: data.public,
         owner,
+        round_duration_secs: data.round_duration_secs,
+        scoring: data.scoring,
+        filters: data.problems,
     };
 
-    let problems = problem_repo.get_from_filters(&data.problems).await?;
+    // checks the filters match something; `Unsolved` ones are picked again
+    // for everyone in the room when a round begins
+    let solved = submission_repo
+        .get_solved_problem_ids(&[config.owner.id])
+        .await?;
+    let problems = problem_repo
+        .get_from_filters(&config.filters, &solved)
+        .await?;
 
     log::info!("{:?}", problems);
 
-    let room = Room::new(id, problems, config.clone());
+    let room = Room::new(
+        id,
+        problems,
+        config.clone(),
+        problem_repo,
+        submission_repo,
+        match_repo,
+        rating_repo,
+    );
     state
         .write()
         .rooms
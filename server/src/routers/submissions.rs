@@ -0,0 +1,40 @@
+use axum::{extract::Path, routing::get, Json, Router};
+
+use crate::{
+    errors::RouteErr,
+    models::{
+        submission::{Submission, SubmissionRepo},
+        user::User,
+    },
+    mongo::ToObjectId,
+    AppState,
+};
+
+pub fn submission_routes() -> Router<AppState> {
+    Router::new()
+        .route("/problem/:problem_id", get(get_for_problem))
+        .route("/:id", get(get_by_id))
+}
+
+/// The user's own submissions to a problem, newest first.
+async fn get_for_problem(
+    user: User,
+    Path(problem_id): Path<String>,
+    submission_repo: SubmissionRepo,
+) -> Result<Json<Vec<Submission>>, RouteErr> {
+    let submissions = submission_repo
+        .get_for_problem(&user.id, &problem_id.to_object_id()?)
+        .await?;
+    Ok(Json(submissions))
+}
+
+async fn get_by_id(
+    user: User,
+    Path(id): Path<String>,
+    submission_repo: SubmissionRepo,
+) -> Result<Json<Submission>, RouteErr> {
+    let submission = submission_repo
+        .get_by_id(&id.to_object_id()?, &user.id)
+        .await?;
+    Ok(Json(submission))
+}